@@ -1,5 +1,5 @@
 use std::process::Command;
 
 fn main() {
-  Command::new("docker").args(&["start", "db"]).status().unwrap();
+  Command::new("docker").args(["start", "db"]).status().unwrap();
 }
\ No newline at end of file
@@ -0,0 +1,50 @@
+-- Split the free-text price into an exact amount and an ISO 4217 currency.
+-- Legacy strings that can't be read ("call us", "12.000,50", ...) keep their
+-- original text in price_legacy with a NULL amount so they can be fixed by
+-- hand, and so do amounts too large for NUMERIC(14, 2) ("999999999999M").
+-- Strings without a currency marker are assumed to be USD.
+CREATE FUNCTION pg_temp.parse_legacy_price(raw TEXT, OUT amount NUMERIC, OUT currency TEXT) AS $$
+DECLARE
+    s TEXT := upper(btrim(raw));
+    m TEXT[];
+BEGIN
+    currency := CASE
+        WHEN s ~ '\$|USD' THEN 'USD'
+        WHEN s ~ '€|EUR' THEN 'EUR'
+        WHEN s ~ '£|GBP' THEN 'GBP'
+        WHEN s ~ '₺|TRY|\mTL\M' THEN 'TRY'
+        ELSE 'USD'
+    END;
+    s := btrim(regexp_replace(s, '\$|€|£|₺|USD|EUR|GBP|TRY|\mTL\M', '', 'g'));
+    m := regexp_match(s, '^([0-9]{1,3}(?:,[0-9]{3})+|[0-9]+)(\.[0-9]{1,2})?\s*([KM])?$');
+    IF m IS NULL THEN
+        amount := NULL;
+        currency := NULL;
+        RETURN;
+    END IF;
+    amount := (replace(m[1], ',', '') || coalesce(m[2], ''))::NUMERIC
+        * CASE m[3] WHEN 'K' THEN 1000 WHEN 'M' THEN 1000000 ELSE 1 END;
+    IF amount >= 1e12 THEN
+        amount := NULL;
+        currency := NULL;
+    END IF;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE vehicles
+    ADD COLUMN price_amount NUMERIC(14, 2),
+    ADD COLUMN price_currency CHAR(3),
+    ADD COLUMN price_legacy TEXT;
+
+UPDATE vehicles SET (price_amount, price_currency) =
+    (SELECT amount, currency FROM pg_temp.parse_legacy_price(price));
+UPDATE vehicles SET price_legacy = price WHERE price_amount IS NULL;
+
+ALTER TABLE vehicles
+    DROP COLUMN price,
+    ADD CONSTRAINT vehicles_price_pair CHECK ((price_amount IS NULL) = (price_currency IS NULL)),
+    ADD CONSTRAINT vehicles_price_present CHECK (price_amount IS NOT NULL OR price_legacy IS NOT NULL),
+    ADD CONSTRAINT vehicles_price_non_negative CHECK (price_amount >= 0),
+    ADD CONSTRAINT vehicles_price_currency_format CHECK (price_currency ~ '^[A-Z]{3}$');
+
+CREATE INDEX vehicles_price_amount_idx ON vehicles (price_currency, price_amount);
@@ -0,0 +1,28 @@
+-- Turn the free-text data column into a JSONB object of specifications.
+-- Values that already hold a JSON object are kept as is; any other text is
+-- preserved under a "notes" key.
+CREATE FUNCTION pg_temp.legacy_data_to_specs(raw TEXT) RETURNS JSONB AS $$
+DECLARE
+    parsed JSONB;
+BEGIN
+    IF btrim(raw) = '' THEN
+        RETURN '{}'::JSONB;
+    END IF;
+    BEGIN
+        parsed := raw::JSONB;
+    EXCEPTION WHEN others THEN
+        parsed := NULL;
+    END;
+    IF jsonb_typeof(parsed) = 'object' THEN
+        RETURN parsed;
+    END IF;
+    RETURN jsonb_build_object('notes', raw);
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE vehicles
+    ALTER COLUMN data TYPE JSONB USING pg_temp.legacy_data_to_specs(data),
+    ALTER COLUMN data SET DEFAULT '{}'::JSONB,
+    ADD CONSTRAINT vehicles_data_is_object CHECK (jsonb_typeof(data) = 'object');
+
+CREATE INDEX vehicles_data_idx ON vehicles USING GIN (data);
@@ -0,0 +1,9 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Text that fuzzy search matches against: the descriptive columns plus every
+-- specification value.
+CREATE FUNCTION vehicle_search_document(manufacturer TEXT, model TEXT, vehicle_type TEXT, data JSONB)
+RETURNS TEXT AS $$
+    SELECT lower(concat_ws(' ', manufacturer, model, vehicle_type,
+        (SELECT string_agg(value, ' ') FROM jsonb_each_text(data))))
+$$ LANGUAGE SQL IMMUTABLE;
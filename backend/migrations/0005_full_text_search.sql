@@ -0,0 +1,10 @@
+-- Maintained full-text index over every text column. Manufacturer and model
+-- weigh most, then vehicle type, then specification values.
+ALTER TABLE vehicles ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', manufacturer), 'A')
+    || setweight(to_tsvector('english', model), 'A')
+    || setweight(to_tsvector('english', vehicle_type), 'B')
+    || setweight(jsonb_to_tsvector('english', data, '["string", "numeric", "boolean"]'), 'C')
+) STORED;
+
+CREATE INDEX vehicles_search_vector_idx ON vehicles USING GIN (search_vector);
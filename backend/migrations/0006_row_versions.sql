@@ -0,0 +1,16 @@
+-- Every write bumps the row version, which the API exposes as an ETag for
+-- optimistic concurrency control.
+ALTER TABLE vehicles
+    ADD COLUMN version INTEGER NOT NULL DEFAULT 1,
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE FUNCTION vehicles_bump_version() RETURNS TRIGGER AS $$
+BEGIN
+    NEW.version := OLD.version + 1;
+    NEW.updated_at := now();
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER vehicles_bump_version BEFORE UPDATE ON vehicles
+    FOR EACH ROW EXECUTE FUNCTION vehicles_bump_version();
@@ -0,0 +1,9 @@
+-- Deleting a vehicle moves it to the trash instead of dropping the row.
+-- Trashed rows are hidden from listing and search, can be restored, and are
+-- purged for good once they are older than the configured retention.
+
+ALTER TABLE vehicles
+    ADD COLUMN deleted_at TIMESTAMPTZ,
+    ADD COLUMN deleted_by TEXT;
+
+CREATE INDEX vehicles_deleted_at_idx ON vehicles (deleted_at) WHERE deleted_at IS NOT NULL;
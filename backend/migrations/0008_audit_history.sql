@@ -0,0 +1,92 @@
+-- Append-only change history. A trigger records every insert, update and
+-- delete of a vehicle with a field-level before/after diff. The actor is read
+-- from the transaction-local `vehicles.actor` setting the API sets before
+-- writing.
+
+CREATE TABLE vehicle_history (
+    id BIGSERIAL PRIMARY KEY,
+    -- No foreign key: the history outlives purged vehicles.
+    vehicle_id INTEGER NOT NULL,
+    action TEXT NOT NULL CHECK (action IN ('create', 'update', 'delete', 'restore', 'purge')),
+    changes JSONB NOT NULL,
+    actor TEXT,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX vehicle_history_vehicle_idx ON vehicle_history (vehicle_id, changed_at);
+
+CREATE FUNCTION vehicle_history_append_only() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'vehicle_history is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER vehicle_history_append_only BEFORE UPDATE OR DELETE ON vehicle_history
+    FOR EACH STATEMENT EXECUTE FUNCTION vehicle_history_append_only();
+CREATE TRIGGER vehicle_history_no_truncate BEFORE TRUNCATE ON vehicle_history
+    FOR EACH STATEMENT EXECUTE FUNCTION vehicle_history_append_only();
+
+-- The audited fields of a vehicle, named like the API names them. Each spec
+-- is its own `spec.<key>` field so a diff shows exactly which one changed.
+-- Unset fields are left out and show up as `null` in a diff.
+CREATE FUNCTION vehicle_audit_fields(v vehicles) RETURNS JSONB AS $$
+    SELECT jsonb_strip_nulls(jsonb_build_object(
+        'vehicle_type', v.vehicle_type,
+        'manufacturer', v.manufacturer,
+        'model', v.model,
+        'price.amount', v.price_amount::TEXT,
+        'price.currency', v.price_currency,
+        'price_legacy', v.price_legacy
+    )) || COALESCE((SELECT jsonb_object_agg('spec.' || key, value) FROM jsonb_each(v.data)), '{}')
+$$ LANGUAGE sql STABLE;
+
+-- `{"field": {"before": ..., "after": ...}}` for every field that differs.
+CREATE FUNCTION vehicle_audit_diff(old_fields JSONB, new_fields JSONB) RETURNS JSONB AS $$
+    SELECT COALESCE(
+        jsonb_object_agg(key, jsonb_build_object('before', old_fields -> key, 'after', new_fields -> key)),
+        '{}'
+    )
+    FROM (SELECT jsonb_object_keys(old_fields) AS key UNION SELECT jsonb_object_keys(new_fields)) keys
+    WHERE (old_fields -> key) IS DISTINCT FROM (new_fields -> key)
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE FUNCTION vehicles_audit() RETURNS TRIGGER AS $$
+DECLARE
+    target_id INTEGER;
+    audit_action TEXT;
+    old_fields JSONB := '{}';
+    new_fields JSONB := '{}';
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        target_id := NEW.id;
+        audit_action := 'create';
+        new_fields := vehicle_audit_fields(NEW);
+    ELSIF TG_OP = 'DELETE' THEN
+        target_id := OLD.id;
+        audit_action := 'purge';
+        old_fields := vehicle_audit_fields(OLD);
+    ELSE
+        target_id := NEW.id;
+        old_fields := vehicle_audit_fields(OLD);
+        new_fields := vehicle_audit_fields(NEW);
+        IF OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN
+            audit_action := 'delete';
+        ELSIF OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN
+            audit_action := 'restore';
+        ELSE
+            audit_action := 'update';
+        END IF;
+    END IF;
+    INSERT INTO vehicle_history (vehicle_id, action, changes, actor)
+    VALUES (
+        target_id,
+        audit_action,
+        vehicle_audit_diff(old_fields, new_fields),
+        NULLIF(current_setting('vehicles.actor', true), '')
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER vehicles_audit AFTER INSERT OR UPDATE OR DELETE ON vehicles
+    FOR EACH ROW EXECUTE FUNCTION vehicles_audit();
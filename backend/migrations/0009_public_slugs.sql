@@ -0,0 +1,23 @@
+-- QR codes encode a random public slug instead of the sequential id, so one
+-- sticker doesn't lead to the rest of the fleet. A NULL slug is revoked.
+CREATE FUNCTION new_public_slug() RETURNS TEXT AS $$
+    -- 122 random bits from a v4 UUID, as 22 URL-safe base64 characters.
+    SELECT rtrim(translate(encode(uuid_send(gen_random_uuid()), 'base64'), '+/', '-_'), '=')
+$$ LANGUAGE sql VOLATILE;
+
+-- The default is volatile, so every existing row gets its own slug.
+ALTER TABLE vehicles ADD COLUMN public_slug TEXT UNIQUE DEFAULT new_public_slug();
+
+-- Record slug changes in the history too, so a revoked or regenerated
+-- sticker can be traced back to who did it.
+CREATE OR REPLACE FUNCTION vehicle_audit_fields(v vehicles) RETURNS JSONB AS $$
+    SELECT jsonb_strip_nulls(jsonb_build_object(
+        'vehicle_type', v.vehicle_type,
+        'manufacturer', v.manufacturer,
+        'model', v.model,
+        'price.amount', v.price_amount::TEXT,
+        'price.currency', v.price_currency,
+        'price_legacy', v.price_legacy,
+        'public_slug', v.public_slug
+    )) || COALESCE((SELECT jsonb_object_agg('spec.' || key, value) FROM jsonb_each(v.data)), '{}')
+$$ LANGUAGE sql STABLE;
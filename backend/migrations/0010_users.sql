@@ -0,0 +1,25 @@
+-- Accounts for the people and scripts that change vehicles. Passwords are
+-- stored as Argon2 PHC strings.
+CREATE TABLE users (
+    id SERIAL PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE CHECK (username <> ''),
+    password_hash TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Login sessions and long-lived API tokens. Only a SHA-256 of the token is
+-- kept, so a leaked table can't be used to sign in.
+CREATE TABLE auth_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    token_hash BYTEA NOT NULL UNIQUE,
+    kind TEXT NOT NULL CHECK (kind IN ('session', 'api')),
+    -- What an API token is for, e.g. `nightly import`.
+    name TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- NULL for API tokens, which last until revoked.
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX auth_tokens_user_id ON auth_tokens (user_id);
@@ -0,0 +1,9 @@
+-- What each account may do: viewers read, editors also add and change
+-- vehicles, admins also delete vehicles and manage accounts.
+ALTER TABLE users
+    ADD COLUMN role TEXT NOT NULL DEFAULT 'viewer'
+        CHECK (role IN ('viewer', 'editor', 'admin'));
+
+-- Everyone could do everything before roles existed; keep it that way for
+-- existing accounts and let an admin narrow it down.
+UPDATE users SET role = 'admin';
@@ -0,0 +1,13 @@
+-- Which vehicle fields the public QR page shows: `vehicle_type`,
+-- `manufacturer`, `model`, `price` or `spec.<key>`. Fields without a row
+-- are internal, so new specification keys stay hidden until made public.
+CREATE TABLE field_visibility (
+    field TEXT PRIMARY KEY,
+    public BOOLEAN NOT NULL
+);
+
+INSERT INTO field_visibility (field, public) VALUES
+    ('vehicle_type', true),
+    ('manufacturer', true),
+    ('model', true),
+    ('price', false);
@@ -0,0 +1,4 @@
+-- Lets fuzzy search find candidates with the `<%` operator instead of
+-- scoring every vehicle.
+CREATE INDEX vehicles_search_document_trgm_idx ON vehicles
+    USING GIN (vehicle_search_document(manufacturer, model, vehicle_type, data) gin_trgm_ops);
@@ -0,0 +1,538 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use deadpool_postgres::Pool;
+use rocket::fairing::AdHoc;
+use rocket::http::{Cookie, CookieJar, SameSite, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::time::Duration;
+use rocket::State;
+use std::fmt;
+use std::ops::Deref;
+
+use crate::db;
+use crate::error::{fail, ApiError};
+
+/// Cookie holding the session token of a browser login.
+pub const SESSION_COOKIE: &str = "session";
+const MIN_PASSWORD_LENGTH: usize = 8;
+/// Checked against when the username is unknown, so that failing takes as
+/// long as a wrong password and doesn't reveal which accounts exist. Made
+/// with the default Argon2 parameters from a password no account has.
+const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$g7vDb9Kc5e44ed8qAEVmxA$lJb8Y2e1dPLCVAs6rX7CFGZaYalKMdLHDbDMki9SS+0";
+
+/// Settings read from the `auth` table of the active Rocket profile.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AuthConfig {
+    /// Hours a login stays valid.
+    #[serde(default = "default_session_hours")]
+    pub session_hours: i32,
+    /// Admin account created at startup while there are no users at all, so
+    /// the first person can sign in.
+    pub bootstrap_username: Option<String>,
+    pub bootstrap_password: Option<String>,
+    /// Marks the session cookie `Secure`. Always on when Rocket itself
+    /// serves TLS; set it when a proxy terminates https in front of it.
+    #[serde(default)]
+    pub secure_cookie: bool,
+}
+
+fn default_session_hours() -> i32 {
+    12
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            session_hours: default_session_hours(),
+            bootstrap_username: None,
+            bootstrap_password: None,
+            secure_cookie: false,
+        }
+    }
+}
+
+/// What an account may do. Each role can do everything the ones before it
+/// can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Role {
+    /// Reads vehicles, their history and QR codes.
+    Viewer,
+    /// Also adds and changes vehicles.
+    Editor,
+    /// Also deletes vehicles and manages accounts.
+    Admin,
+}
+
+impl Role {
+    /// The name stored in `users.role`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Role> {
+        match value {
+            "viewer" => Some(Role::Viewer),
+            "editor" => Some(Role::Editor),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Reads a `users.role` value; the column's check constraint keeps it valid.
+pub fn role_from_db(value: &str) -> Result<Role, ApiError> {
+    Role::parse(value).ok_or_else(|| ApiError::Internal(format!("Unknown role `{}`", value)))
+}
+
+/// The signed-in user, from an `Authorization: Bearer <token>` header or the
+/// session cookie. Routes that take it answer 401 without valid credentials.
+/// Every account is at least a viewer, so read routes take this guard.
+pub struct User {
+    pub id: i32,
+    pub username: String,
+    pub role: Role,
+    /// The session or API token the request was made with.
+    token_id: i32,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for User {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let bearer = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+        let token = match bearer.or_else(|| {
+            req.cookies()
+                .get(SESSION_COOKIE)
+                .map(|cookie| cookie.value().to_string())
+        }) {
+            Some(token) => token,
+            None => {
+                return fail(
+                    req,
+                    ApiError::Unauthorized("Sign in or send an API token".to_string()),
+                )
+            }
+        };
+        let Some(pool) = req.rocket().state::<Pool>() else {
+            return fail(req, ApiError::Internal("No database pool".to_string()));
+        };
+        match authenticate(pool, &token).await {
+            Ok(Some(user)) => Outcome::Success(user),
+            Ok(None) => fail(
+                req,
+                ApiError::Unauthorized("The token is invalid or has expired".to_string()),
+            ),
+            Err(e) => fail(req, e),
+        }
+    }
+}
+
+/// A signed-in editor or admin. Answers 403 for viewers.
+pub struct Editor(User);
+
+/// A signed-in admin. Answers 403 for everyone else.
+pub struct Admin(User);
+
+async fn with_role(req: &Request<'_>, role: Role) -> Outcome<User, ApiError> {
+    match req.guard::<User>().await {
+        Outcome::Success(user) if user.role >= role => Outcome::Success(user),
+        Outcome::Success(user) => fail(
+            req,
+            ApiError::Forbidden(format!(
+                "This needs the {} role; `{}` only has the {} role",
+                role, user.username, user.role
+            )),
+        ),
+        Outcome::Error(e) => Outcome::Error(e),
+        Outcome::Forward(status) => Outcome::Forward(status),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Editor {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        with_role(req, Role::Editor).await.map(Editor)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        with_role(req, Role::Admin).await.map(Admin)
+    }
+}
+
+impl Deref for Editor {
+    type Target = User;
+
+    fn deref(&self) -> &User {
+        &self.0
+    }
+}
+
+impl Deref for Admin {
+    type Target = User;
+
+    fn deref(&self) -> &User {
+        &self.0
+    }
+}
+
+/// How long `last_used_at` may lag behind, so requests read the token
+/// instead of writing it every time.
+const LAST_USED_RESOLUTION: &str = "1 minute";
+
+async fn authenticate(pool: &Pool, token: &str) -> Result<Option<User>, ApiError> {
+    let client = db::client(pool).await?;
+    let Some(row) = client
+        .query_opt(
+            &format!(
+                "SELECT u.id, u.username, u.role, t.id,
+                    t.last_used_at IS NULL OR t.last_used_at < now() - interval '{}'
+                FROM auth_tokens t JOIN users u ON u.id = t.user_id
+                WHERE t.token_hash = sha256(convert_to($1, 'UTF8'))
+                    AND (t.expires_at IS NULL OR t.expires_at > now())",
+                LAST_USED_RESOLUTION
+            ),
+            &[&token],
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+    let user = User {
+        id: row.get(0),
+        username: row.get(1),
+        role: role_from_db(row.get(2))?,
+        token_id: row.get(3),
+    };
+    if row.get::<_, bool>(4) {
+        // Repeats the staleness check so concurrent requests write it once.
+        client
+            .execute(
+                &format!(
+                    "UPDATE auth_tokens SET last_used_at = now()
+                    WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < now() - interval '{}')",
+                    LAST_USED_RESOLUTION
+                ),
+                &[&user.token_id],
+            )
+            .await?;
+    }
+    Ok(Some(user))
+}
+
+/// A random 256-bit token, hex encoded.
+fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hashes a password for the `users` table. Argon2 is slow on purpose, so
+/// it runs off the async workers.
+pub async fn hash_password(password: String) -> Result<String, ApiError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(ApiError::Validation(format!(
+            "Passwords need at least {} characters",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| ApiError::Internal(e.to_string()))
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))?
+}
+
+async fn verify_password(password: String, hash: String) -> Result<bool, ApiError> {
+    tokio::task::spawn_blocking(move || {
+        let hash = PasswordHash::new(&hash).map_err(|e| ApiError::Internal(e.to_string()))?;
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok())
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))?
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Session {
+    /// Send as `Authorization: Bearer <token>`; browsers also get it as the
+    /// session cookie.
+    pub token: String,
+    pub username: String,
+    pub role: Role,
+    pub expires_at: String,
+}
+
+/// Signs in with a username and password and starts a session.
+#[post("/api/auth/login", data = "<credentials>")]
+pub async fn login(
+    pool: &State<Pool>,
+    config: &State<AuthConfig>,
+    cookies: &CookieJar<'_>,
+    credentials: Json<Credentials>,
+) -> Result<Json<Session>, ApiError> {
+    let Credentials { username, password } = credentials.into_inner();
+    let client = db::client(pool).await?;
+    let user = client
+        .query_opt(
+            "SELECT id, password_hash, role FROM users WHERE username = $1",
+            &[&username],
+        )
+        .await?;
+    let wrong = || ApiError::Unauthorized("Wrong username or password".to_string());
+    let hash = user.as_ref().map_or(DUMMY_HASH.to_string(), |row| row.get(1));
+    let verified = verify_password(password, hash).await?;
+    let row = match user {
+        Some(row) if verified => row,
+        _ => return Err(wrong()),
+    };
+    let user_id: i32 = row.get(0);
+    let role = role_from_db(row.get(2))?;
+
+    let token = new_token();
+    client
+        .execute(
+            "DELETE FROM auth_tokens WHERE expires_at < now()",
+            &[],
+        )
+        .await?;
+    let expires_at: String = client
+        .query_one(
+            "INSERT INTO auth_tokens (user_id, token_hash, kind, expires_at)
+            VALUES ($1, sha256(convert_to($2, 'UTF8')), 'session', now() + make_interval(hours => $3))
+            RETURNING expires_at::TEXT",
+            &[&user_id, &token, &config.session_hours],
+        )
+        .await?
+        .get(0);
+    cookies.add(
+        Cookie::build((SESSION_COOKIE, token.clone()))
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .secure(config.secure_cookie)
+            .max_age(Duration::hours(config.session_hours.into())),
+    );
+    Ok(Json(Session {
+        token,
+        username,
+        role,
+        expires_at,
+    }))
+}
+
+/// Ends the session, or revokes the API token, the request was made with.
+#[post("/api/auth/logout")]
+pub async fn logout(
+    pool: &State<Pool>,
+    cookies: &CookieJar<'_>,
+    user: User,
+) -> Result<Status, ApiError> {
+    let client = db::client(pool).await?;
+    client
+        .execute("DELETE FROM auth_tokens WHERE id = $1", &[&user.token_id])
+        .await?;
+    cookies.remove(SESSION_COOKIE);
+    Ok(Status::NoContent)
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Me {
+    pub id: i32,
+    pub username: String,
+    pub role: Role,
+}
+
+/// Who the credentials belong to.
+#[get("/api/auth/me")]
+pub async fn me(user: User) -> Json<Me> {
+    Json(Me {
+        id: user.id,
+        username: user.username,
+        role: user.role,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NewApiToken {
+    pub name: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiToken {
+    pub id: i32,
+    pub name: Option<String>,
+    /// Only returned when the token is created; it can't be looked up later.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    pub created_at: String,
+    /// Lags behind by up to `LAST_USED_RESOLUTION`.
+    pub last_used_at: Option<String>,
+}
+
+/// The API tokens of the signed-in user.
+#[get("/api/auth/tokens")]
+pub async fn get_api_tokens(pool: &State<Pool>, user: User) -> Result<Json<Vec<ApiToken>>, ApiError> {
+    let client = db::client(pool).await?;
+    let tokens = client
+        .query(
+            "SELECT id, name, created_at::TEXT, last_used_at::TEXT FROM auth_tokens
+            WHERE user_id = $1 AND kind = 'api'
+            ORDER BY id",
+            &[&user.id],
+        )
+        .await?
+        .iter()
+        .map(|row| ApiToken {
+            id: row.get(0),
+            name: row.get(1),
+            token: None,
+            created_at: row.get(2),
+            last_used_at: row.get(3),
+        })
+        .collect();
+    Ok(Json(tokens))
+}
+
+/// Creates a long-lived token for scripts, acting as the signed-in user.
+#[post("/api/auth/tokens", data = "<new_token>")]
+pub async fn create_api_token(
+    pool: &State<Pool>,
+    user: User,
+    new_token: Json<NewApiToken>,
+) -> Result<Json<ApiToken>, ApiError> {
+    let name = new_token.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::Validation("Give the token a name".to_string()));
+    }
+    let token = self::new_token();
+    let client = db::client(pool).await?;
+    let row = client
+        .query_one(
+            "INSERT INTO auth_tokens (user_id, token_hash, kind, name)
+            VALUES ($1, sha256(convert_to($2, 'UTF8')), 'api', $3)
+            RETURNING id, created_at::TEXT",
+            &[&user.id, &token, &name],
+        )
+        .await?;
+    Ok(Json(ApiToken {
+        id: row.get(0),
+        name: Some(name.to_string()),
+        token: Some(token),
+        created_at: row.get(1),
+        last_used_at: None,
+    }))
+}
+
+#[delete("/api/auth/tokens/<id>")]
+pub async fn revoke_api_token(pool: &State<Pool>, user: User, id: i32) -> Result<Status, ApiError> {
+    let client = db::client(pool).await?;
+    let revoked = client
+        .execute(
+            "DELETE FROM auth_tokens WHERE id = $1 AND user_id = $2 AND kind = 'api'",
+            &[&id, &user.id],
+        )
+        .await?;
+    match revoked {
+        0 => Err(ApiError::NotFound("You have no API token with this ID".to_string())),
+        _ => Ok(Status::NoContent),
+    }
+}
+
+/// Reads the `auth` settings and, while there are no users yet, creates the
+/// bootstrap account.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Authentication", |rocket| async {
+        let mut config = match rocket.figment().extract_inner::<AuthConfig>("auth") {
+            Ok(config) => config,
+            Err(e) if e.missing() => AuthConfig::default(),
+            Err(e) => {
+                error!("Invalid auth settings: {}", e);
+                return Err(rocket);
+            }
+        };
+        let tls = rocket
+            .figment()
+            .extract::<rocket::Config>()
+            .is_ok_and(|rocket_config| rocket_config.tls_enabled());
+        config.secure_cookie |= tls;
+        if config.session_hours < 1 {
+            error!("auth.session_hours must be at least 1");
+            return Err(rocket);
+        }
+        let Some(pool) = rocket.state::<Pool>() else {
+            return Err(rocket);
+        };
+        if let Err(e) = bootstrap(pool, &config).await {
+            warn!("Can't create the bootstrap user: {}", e.message());
+        }
+        Ok(rocket.manage(config))
+    })
+}
+
+async fn bootstrap(pool: &Pool, config: &AuthConfig) -> Result<(), ApiError> {
+    let client = db::client(pool).await?;
+    let users: i64 = client
+        .query_one("SELECT COUNT(*) FROM users", &[])
+        .await?
+        .get(0);
+    if users > 0 {
+        return Ok(());
+    }
+    let (Some(username), Some(password)) = (&config.bootstrap_username, &config.bootstrap_password)
+    else {
+        warn!("There are no users yet; set auth.bootstrap_username and auth.bootstrap_password to create one");
+        return Ok(());
+    };
+    let password_hash = hash_password(password.clone()).await?;
+    client
+        .execute(
+            "INSERT INTO users (username, password_hash, role) VALUES ($1, $2, 'admin')",
+            &[username, &password_hash],
+        )
+        .await?;
+    info!("Created the bootstrap user `{}`", username);
+    Ok(())
+}
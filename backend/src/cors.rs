@@ -0,0 +1,307 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use rocket::fairing::AdHoc;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_cors::{AllowedHeaders, AllowedOrigins, CorsOptions, Method};
+
+/// Settings read from the `cors` table of the active Rocket profile. They
+/// decide which websites may call the API from a user's browser.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CorsConfig {
+    /// Origins such as `https://fleet.example.com`, or `["*"]` for any.
+    /// Empty allows no cross-origin callers: every request carrying an
+    /// `Origin` header is refused, including same-origin writes from a page
+    /// served by this host, which browsers also send it with. Requests
+    /// without one, such as scripts and `curl`, are unaffected.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    /// Request headers pages may send, besides the ones browsers always
+    /// allow.
+    #[serde(default = "default_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+    /// Whether pages may send the session cookie. Not allowed together with
+    /// `*`, since any website could then act as the signed-in user.
+    #[serde(default)]
+    pub allow_credentials: bool,
+}
+
+fn default_allowed_methods() -> Vec<String> {
+    ["GET", "POST", "PUT", "PATCH", "DELETE"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn default_allowed_headers() -> Vec<String> {
+    ["Authorization", "Content-Type", "If-Match"]
+        .map(String::from)
+        .to_vec()
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: default_allowed_methods(),
+            allowed_headers: default_allowed_headers(),
+            allow_credentials: false,
+        }
+    }
+}
+
+impl CorsConfig {
+    fn any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    fn options(&self) -> Result<CorsOptions, String> {
+        let allowed_origins = match self.any_origin() {
+            true if self.allow_credentials => {
+                return Err("cors.allow_credentials can't be used with the `*` origin".to_string())
+            }
+            true if self.allowed_origins.len() > 1 => {
+                return Err("cors.allowed_origins can't list origins next to `*`".to_string())
+            }
+            true => AllowedOrigins::all(),
+            false => AllowedOrigins::some_exact(&self.allowed_origins),
+        };
+        let allowed_methods = self
+            .allowed_methods
+            .iter()
+            .map(|method| {
+                Method::from_str(&method.to_uppercase())
+                    .map_err(|_| format!("cors.allowed_methods: unknown method `{}`", method))
+            })
+            .collect::<Result<HashSet<_>, _>>()?;
+        let allowed_headers: Vec<&str> = self.allowed_headers.iter().map(String::as_str).collect();
+        Ok(CorsOptions::default()
+            .allowed_origins(allowed_origins)
+            .allowed_methods(allowed_methods)
+            .allowed_headers(AllowedHeaders::some(&allowed_headers))
+            .allow_credentials(self.allow_credentials)
+            .expose_headers(["ETag".to_string()].into_iter().collect()))
+    }
+}
+
+/// The origins a browser sends in `Origin` are lowercase and have no
+/// trailing slash.
+fn normalize(origin: &str) -> String {
+    origin.trim_end_matches('/').to_ascii_lowercase()
+}
+
+/// Reads the `cors` settings and attaches the CORS policy, plus a request
+/// fairing that logs requests from origins the policy refuses.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("CORS", |rocket| async {
+        let config = match rocket.figment().extract_inner::<CorsConfig>("cors") {
+            Ok(config) => config,
+            Err(e) if e.missing() => CorsConfig::default(),
+            Err(e) => {
+                error!("Invalid cors settings: {}", e);
+                return Err(rocket);
+            }
+        };
+        let cors = match config
+            .options()
+            .and_then(|options| options.to_cors().map_err(|e| e.to_string()))
+        {
+            Ok(cors) => cors,
+            Err(e) => {
+                error!("Invalid cors settings: {}", e);
+                return Err(rocket);
+            }
+        };
+        if config.allowed_origins.is_empty() {
+            warn!("cors.allowed_origins is empty; requests from browsers that send Origin are refused");
+        }
+        // `None` when every origin is allowed.
+        let allowed: Option<Arc<HashSet<String>>> = match config.any_origin() {
+            true => None,
+            false => Some(Arc::new(
+                config
+                    .allowed_origins
+                    .iter()
+                    .map(|o| normalize(o))
+                    .collect(),
+            )),
+        };
+        let log_refused = AdHoc::on_request("CORS refusals", move |req, _| {
+            if let (Some(allowed), Some(origin)) = (&allowed, req.headers().get_one("Origin")) {
+                if !allowed.contains(&normalize(origin)) {
+                    warn!(
+                        "Refused {} {} from origin {}",
+                        req.method(),
+                        req.uri(),
+                        origin
+                    );
+                }
+            }
+            Box::pin(async {})
+        });
+        // The logger runs first, before the policy reroutes refused requests.
+        Ok(rocket.attach(log_refused).attach(cors))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::error::ErrorKind;
+    use rocket::figment::Figment;
+    use rocket::http::{Header, Method, Status};
+    use rocket::local::blocking::Client;
+
+    use super::CorsConfig;
+
+    #[get("/api/vehicles")]
+    fn vehicles() -> &'static str {
+        "[]"
+    }
+
+    fn client(config: CorsConfig) -> Client {
+        let figment = Figment::from(rocket::Config::debug_default()).merge(("cors", config));
+        let rocket = rocket::custom(figment)
+            .attach(super::stage())
+            .mount("/", routes![vehicles]);
+        Client::tracked(rocket).expect("valid rocket")
+    }
+
+    fn frontend() -> CorsConfig {
+        CorsConfig {
+            allowed_origins: vec!["https://fleet.example.com".to_string()],
+            allow_credentials: true,
+            ..CorsConfig::default()
+        }
+    }
+
+    fn preflight<'c>(
+        client: &'c Client,
+        origin: &str,
+        method: &str,
+        headers: &str,
+    ) -> rocket::local::blocking::LocalResponse<'c> {
+        client
+            .req(Method::Options, "/api/vehicles")
+            .header(Header::new("Origin", origin.to_string()))
+            .header(Header::new(
+                "Access-Control-Request-Method",
+                method.to_string(),
+            ))
+            .header(Header::new(
+                "Access-Control-Request-Headers",
+                headers.to_string(),
+            ))
+            .dispatch()
+    }
+
+    #[test]
+    fn preflight_from_allowed_origin_succeeds() {
+        let client = client(frontend());
+        let response = preflight(
+            &client,
+            "https://fleet.example.com",
+            "DELETE",
+            "authorization, if-match",
+        );
+        // There's no OPTIONS route; the policy answers the preflight itself.
+        assert_eq!(response.status(), Status::NoContent);
+        let headers = response.headers();
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Origin"),
+            Some("https://fleet.example.com")
+        );
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Credentials"),
+            Some("true")
+        );
+        let methods = headers
+            .get_one("Access-Control-Allow-Methods")
+            .unwrap_or_default();
+        assert!(methods.contains("DELETE"), "{}", methods);
+    }
+
+    #[test]
+    fn preflight_from_other_origin_is_refused() {
+        let client = client(frontend());
+        let response = preflight(
+            &client,
+            "https://evil.example.net",
+            "DELETE",
+            "authorization",
+        );
+        assert_eq!(response.status(), Status::Forbidden);
+        assert_eq!(
+            response.headers().get_one("Access-Control-Allow-Origin"),
+            None
+        );
+    }
+
+    #[test]
+    fn preflight_for_unlisted_method_or_header_is_refused() {
+        let client = client(CorsConfig {
+            allowed_methods: vec!["GET".to_string()],
+            ..frontend()
+        });
+        let response = preflight(
+            &client,
+            "https://fleet.example.com",
+            "DELETE",
+            "authorization",
+        );
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = preflight(&client, "https://fleet.example.com", "GET", "x-secret");
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn simple_request_gets_allow_origin_and_exposed_etag() {
+        let client = client(frontend());
+        let response = client
+            .get("/api/vehicles")
+            .header(Header::new("Origin", "https://fleet.example.com"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Access-Control-Allow-Origin"),
+            Some("https://fleet.example.com")
+        );
+        assert_eq!(
+            response.headers().get_one("Access-Control-Expose-Headers"),
+            Some("ETag")
+        );
+    }
+
+    #[test]
+    fn wildcard_with_credentials_fails_to_start() {
+        let config = CorsConfig {
+            allowed_origins: vec!["*".to_string()],
+            allow_credentials: true,
+            ..CorsConfig::default()
+        };
+        let figment = Figment::from(rocket::Config::debug_default()).merge(("cors", config));
+        let rocket = rocket::custom(figment).attach(super::stage());
+        // Inspecting the error keeps Rocket from panicking when it's dropped.
+        match Client::tracked(rocket) {
+            Err(e) => assert!(matches!(e.kind(), ErrorKind::FailedFairings(_))),
+            Ok(_) => panic!("started with `*` and credentials"),
+        }
+    }
+
+    #[test]
+    fn empty_origin_list_refuses_every_origin() {
+        let client = client(CorsConfig::default());
+        let response = client
+            .get("/api/vehicles")
+            .header(Header::new("Origin", "https://fleet.example.com"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = preflight(&client, "https://fleet.example.com", "GET", "authorization");
+        assert_eq!(response.status(), Status::Forbidden);
+        // Callers that aren't browsers send no Origin and pass.
+        let response = client.get("/api/vehicles").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
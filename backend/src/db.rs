@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use deadpool_postgres::{
+    Manager, ManagerConfig, Object, Pool, PoolError, RecyclingMethod, Runtime, Transaction,
+};
+use postgres_native_tls::MakeTlsConnector;
+use rocket::fairing::AdHoc;
+use rocket::figment::providers::Serialized;
+use rocket::figment::Figment;
+use rocket::serde::Deserialize;
+use rocket::{Build, Rocket};
+use tokio_postgres::config::SslMode;
+use tokio_postgres::error::ErrorPosition;
+use tokio_postgres::Config;
+
+use crate::auth::User;
+use crate::error::ApiError;
+use crate::migrations;
+
+/// Connection settings read from the `database` table of the active Rocket
+/// profile. `DATABASE_URL` overrides `url` when set.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct DatabaseConfig {
+    pub url: String,
+    #[serde(default)]
+    pub tls: TlsMode,
+    /// Seconds to wait for the initial connection.
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+    /// Milliseconds a single statement may run before Postgres cancels it.
+    pub statement_timeout: Option<u64>,
+    /// Maximum number of open connections.
+    #[serde(default = "default_pool_size")]
+    pub pool_size: usize,
+    /// Seconds a request waits for a free connection before giving up.
+    #[serde(default = "default_pool_timeout")]
+    pub pool_timeout: u64,
+    /// Apply pending schema migrations at startup. When disabled, run
+    /// `backend migrate up` before deploying.
+    #[serde(default = "default_migrate")]
+    pub migrate: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum TlsMode {
+    Disable,
+    #[default]
+    Prefer,
+    Require,
+}
+
+fn default_connect_timeout() -> u64 {
+    5
+}
+
+fn default_pool_size() -> usize {
+    16
+}
+
+fn default_pool_timeout() -> u64 {
+    5
+}
+
+fn default_migrate() -> bool {
+    true
+}
+
+impl DatabaseConfig {
+    pub fn from_figment(figment: &Figment) -> Result<Self, String> {
+        let mut figment = figment.clone();
+        if let Ok(url) = std::env::var("DATABASE_URL") {
+            figment = figment.merge(Serialized::global("database.url", url));
+        }
+        figment
+            .extract_inner("database")
+            .map_err(|e| format!("Invalid database configuration: {}", e))
+    }
+
+    pub fn to_pg_config(&self) -> Result<Config, String> {
+        let mut config = self
+            .url
+            .parse::<Config>()
+            .map_err(|e| format!("Invalid database url: {}", e))?;
+        config.ssl_mode(match self.tls {
+            TlsMode::Disable => SslMode::Disable,
+            TlsMode::Prefer => SslMode::Prefer,
+            TlsMode::Require => SslMode::Require,
+        });
+        config.connect_timeout(Duration::from_secs(self.connect_timeout));
+        if let Some(ms) = self.statement_timeout {
+            config.options(format!("-c statement_timeout={}", ms));
+        }
+        Ok(config)
+    }
+
+    pub fn tls_connector(&self) -> Result<MakeTlsConnector, String> {
+        native_tls::TlsConnector::new()
+            .map(MakeTlsConnector::new)
+            .map_err(|e| format!("Failed to set up TLS: {}", e))
+    }
+
+    /// Builds the connection pool. Connections are opened lazily and checked
+    /// with a test query before being handed out, so a dropped connection is
+    /// replaced on the next checkout instead of failing every request.
+    pub fn build_pool(&self) -> Result<Pool, String> {
+        let manager = Manager::from_config(
+            self.to_pg_config()?,
+            self.tls_connector()?,
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Verified,
+            },
+        );
+        Pool::builder(manager)
+            .max_size(self.pool_size)
+            .wait_timeout(Some(Duration::from_secs(self.pool_timeout)))
+            .create_timeout(Some(Duration::from_secs(self.connect_timeout)))
+            .recycle_timeout(Some(Duration::from_secs(self.connect_timeout)))
+            .runtime(Runtime::Tokio1)
+            .build()
+            .map_err(|e| format!("Failed to build connection pool: {}", e))
+    }
+}
+
+/// Formats a Postgres error for logs and the CLI. `Display` only says
+/// "db error", so this adds what the server reported: message, detail,
+/// hint, context and, when the failing `sql` is known, the line the error
+/// points at.
+pub fn describe(e: &tokio_postgres::Error, sql: Option<&str>) -> String {
+    let Some(db_error) = e.as_db_error() else {
+        let mut message = e.to_string();
+        let mut source = std::error::Error::source(e);
+        while let Some(cause) = source {
+            message.push_str(&format!(": {}", cause));
+            source = cause.source();
+        }
+        return message;
+    };
+    let mut message = format!(
+        "{} {}: {}",
+        db_error.severity(),
+        db_error.code().code(),
+        db_error.message()
+    );
+    if let Some(detail) = db_error.detail() {
+        message.push_str(&format!("\nDETAIL: {}", detail));
+    }
+    if let Some(hint) = db_error.hint() {
+        message.push_str(&format!("\nHINT: {}", hint));
+    }
+    if let Some(context) = db_error.where_() {
+        message.push_str(&format!("\nCONTEXT: {}", context));
+    }
+    match (db_error.position(), sql) {
+        // Positions count characters from 1.
+        (Some(ErrorPosition::Original(position)), Some(sql)) => {
+            let before = sql.chars().take((*position as usize).saturating_sub(1)).collect::<String>();
+            let line = before.matches('\n').count();
+            message.push_str(&format!(
+                "\nLINE {}: {}",
+                line + 1,
+                sql.lines().nth(line).unwrap_or_default().trim()
+            ));
+        }
+        (Some(ErrorPosition::Original(position)), None) => {
+            message.push_str(&format!("\nPOSITION: {}", position))
+        }
+        (Some(ErrorPosition::Internal { position, query }), _) => {
+            message.push_str(&format!("\nPOSITION: {} in `{}`", position, query))
+        }
+        (None, _) => {}
+    }
+    message
+}
+
+/// Like `describe`, for failures to get a connection from the pool.
+pub fn describe_pool_error(e: &PoolError) -> String {
+    match e {
+        PoolError::Backend(e) => describe(e, None),
+        e => e.to_string(),
+    }
+}
+
+/// Borrows a connection from the pool.
+pub async fn client(pool: &Pool) -> Result<Object, ApiError> {
+    Ok(pool.get().await?)
+}
+
+/// Starts a transaction whose vehicle changes the history trigger attributes
+/// to `user`. The setting is transaction-local, so it never leaks to the
+/// next user of the pooled connection.
+pub async fn audited<'a>(client: &'a mut Object, user: &User) -> Result<Transaction<'a>, ApiError> {
+    let transaction = client.transaction().await?;
+    transaction
+        .execute(
+            "SELECT set_config('vehicles.actor', $1, true)",
+            &[&user.username],
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn init(rocket: Rocket<Build>) -> Result<Rocket<Build>, Rocket<Build>> {
+    let config = match DatabaseConfig::from_figment(rocket.figment()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            return Err(rocket);
+        }
+    };
+    let pool = match config.build_pool() {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!("{}", e);
+            return Err(rocket);
+        }
+    };
+    let mut client = match pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to connect to Postgres: {}", describe_pool_error(&e));
+            return Err(rocket);
+        }
+    };
+
+    if config.migrate {
+        match migrations::run(&mut client).await {
+            Ok(applied) => applied
+                .iter()
+                .for_each(|m| info!("Applied migration {:04} {}", m.version, m.name)),
+            Err(e) => {
+                error!("Failed to apply migrations: {}", e);
+                return Err(rocket);
+            }
+        }
+    } else {
+        match migrations::pending(&client).await {
+            Ok(pending) if !pending.is_empty() => warn!(
+                "{} pending migration(s); run `backend migrate up`",
+                pending.len()
+            ),
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to read migration status: {}", e);
+                return Err(rocket);
+            }
+        }
+    }
+
+    drop(client);
+    Ok(rocket.manage(pool))
+}
+
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Postgres", init)
+}
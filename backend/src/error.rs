@@ -0,0 +1,223 @@
+use deadpool_postgres::PoolError;
+use rocket::http::Status;
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::request::Outcome;
+use rocket::Request;
+use serde_json::Value;
+use tokio_postgres::error::SqlState;
+
+/// Every failure a handler can return. Each variant maps to one HTTP status
+/// and a stable `code` that clients can match on.
+#[derive(Debug)]
+pub enum ApiError {
+    /// 404: the addressed row does not exist.
+    NotFound(String),
+    /// 400: the request itself is malformed.
+    BadRequest(String),
+    /// 401: the request needs a signed-in user and has none, or the
+    /// credentials are wrong.
+    Unauthorized(String),
+    /// 403: the signed-in user's role doesn't allow the request.
+    Forbidden(String),
+    /// 422: the request is well formed but its values are not acceptable.
+    Validation(String),
+    /// 409: the change collides with existing data.
+    Conflict(String),
+    /// 412: the row changed since the client read it. Carries the current
+    /// state so the client can show or merge it.
+    PreconditionFailed(String, Value),
+    /// 503: the database can't be reached right now.
+    Unavailable(String),
+    /// 500: anything else.
+    Internal(String),
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<Value>,
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::Unauthorized(_) => Status::Unauthorized,
+            ApiError::Forbidden(_) => Status::Forbidden,
+            ApiError::Validation(_) => Status::UnprocessableEntity,
+            ApiError::Conflict(_) => Status::Conflict,
+            ApiError::PreconditionFailed(..) => Status::PreconditionFailed,
+            ApiError::Unavailable(_) => Status::ServiceUnavailable,
+            ApiError::Internal(_) => Status::InternalServerError,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "not_found",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::PreconditionFailed(..) => "precondition_failed",
+            ApiError::Unavailable(_) => "database_unavailable",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::NotFound(m)
+            | ApiError::BadRequest(m)
+            | ApiError::Unauthorized(m)
+            | ApiError::Forbidden(m)
+            | ApiError::Validation(m)
+            | ApiError::Conflict(m)
+            | ApiError::PreconditionFailed(m, _)
+            | ApiError::Unavailable(m)
+            | ApiError::Internal(m) => m,
+        }
+    }
+}
+
+impl From<tokio_postgres::Error> for ApiError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        let message = e.to_string();
+        let Some(db_error) = e.as_db_error() else {
+            // Only a lost connection means the database is unreachable.
+            // Other client-side failures, such as a row that doesn't fit
+            // the Rust type it's read into, are bugs.
+            let connection_lost = e.is_closed()
+                || std::error::Error::source(&e).is_some_and(|source| source.is::<std::io::Error>());
+            return match connection_lost {
+                true => ApiError::Unavailable(message),
+                false => ApiError::Internal(message),
+            };
+        };
+        match *db_error.code() {
+            SqlState::UNIQUE_VIOLATION
+            | SqlState::FOREIGN_KEY_VIOLATION
+            | SqlState::EXCLUSION_VIOLATION => ApiError::Conflict(db_error.message().to_string()),
+            SqlState::NOT_NULL_VIOLATION
+            | SqlState::CHECK_VIOLATION
+            | SqlState::INVALID_TEXT_REPRESENTATION
+            | SqlState::NUMERIC_VALUE_OUT_OF_RANGE => {
+                ApiError::Validation(db_error.message().to_string())
+            }
+            SqlState::QUERY_CANCELED
+            | SqlState::ADMIN_SHUTDOWN
+            | SqlState::CANNOT_CONNECT_NOW
+            | SqlState::TOO_MANY_CONNECTIONS => ApiError::Unavailable(message),
+            _ => ApiError::Internal(message),
+        }
+    }
+}
+
+impl From<PoolError> for ApiError {
+    fn from(e: PoolError) -> Self {
+        ApiError::Unavailable(e.to_string())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status();
+        if status.code >= 500 {
+            error!("{} {}: {}", req.method(), req.uri(), self.message());
+        }
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.message().to_string(),
+            current: match self {
+                ApiError::PreconditionFailed(_, current) => Some(current),
+                _ => None,
+            },
+        };
+        (status, Json(body)).respond_to(req)
+    }
+}
+
+/// Set by a request guard that fails, so the catcher can say why instead of
+/// only naming the status.
+pub struct GuardFailure(pub Option<String>);
+
+/// Fails a request guard with `error`, recording its message for the
+/// catcher.
+pub fn fail<T>(req: &Request<'_>, error: ApiError) -> Outcome<T, ApiError> {
+    req.local_cache(|| GuardFailure(Some(error.message().to_string())));
+    Outcome::Error((error.status(), error))
+}
+
+/// Gives requests that never reach a handler (unknown route, unparsable JSON
+/// body, ...) the same error body as handler failures.
+#[catch(default)]
+pub fn default_catcher(status: Status, req: &Request) -> (Status, Json<ErrorBody>) {
+    let code = match status.code {
+        400 => "bad_request",
+        401 => "unauthorized",
+        403 => "forbidden",
+        404 => "not_found",
+        409 => "conflict",
+        412 => "precondition_failed",
+        422 => "validation_failed",
+        503 => "database_unavailable",
+        500..=599 => "internal_error",
+        _ => "error",
+    };
+    let body = ErrorBody {
+        code,
+        message: req
+            .local_cache(|| GuardFailure(None))
+            .0
+            .clone()
+            .unwrap_or_else(|| status.reason_lossy().to_string()),
+        current: None,
+    };
+    (status, Json(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+    use serde_json::Value;
+
+    use super::ApiError;
+
+    #[get("/status/<code>")]
+    fn status(code: u16) -> Status {
+        Status::from_code(code).unwrap()
+    }
+
+    #[test]
+    fn catcher_uses_the_same_codes_as_handlers() {
+        let rocket = rocket::build()
+            .mount("/", routes![status])
+            .register("/", catchers![super::default_catcher]);
+        let client = Client::tracked(rocket).expect("valid rocket");
+        let errors = [
+            ApiError::NotFound(String::new()),
+            ApiError::BadRequest(String::new()),
+            ApiError::Unauthorized(String::new()),
+            ApiError::Forbidden(String::new()),
+            ApiError::Validation(String::new()),
+            ApiError::Conflict(String::new()),
+            ApiError::PreconditionFailed(String::new(), Value::Null),
+            ApiError::Unavailable(String::new()),
+            ApiError::Internal(String::new()),
+        ];
+        for error in errors {
+            let response = client.get(format!("/status/{}", error.status().code)).dispatch();
+            assert_eq!(response.status(), error.status());
+            let body = response.into_json::<Value>().unwrap();
+            assert_eq!(body["code"], error.code(), "status {}", error.status());
+        }
+    }
+}
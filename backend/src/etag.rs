@@ -0,0 +1,67 @@
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::error::{fail, ApiError};
+
+/// Strong entity tag for a vehicle row version, e.g. `"v3"`.
+pub struct ETag(pub i32);
+
+impl From<ETag> for Header<'static> {
+    fn from(tag: ETag) -> Self {
+        Header::new("ETag", format!("\"v{}\"", tag.0))
+    }
+}
+
+/// Wraps a response with the `ETag` of the vehicle it describes.
+#[derive(Responder)]
+pub struct Tagged<R> {
+    pub inner: R,
+    pub etag: ETag,
+}
+
+/// Versions listed in the request's `If-Match` header. `None` means the
+/// client sent no precondition (or `*`), so the write goes through as before.
+/// Weak tags (`W/"v3"`) are left out: `If-Match` uses strong comparison, so
+/// they never match and a header with only weak tags answers 412.
+pub struct IfMatch(pub Option<Vec<i32>>);
+
+fn parse_if_match(value: &str) -> Result<Option<Vec<i32>>, ApiError> {
+    if value.trim() == "*" {
+        return Ok(None);
+    }
+    value
+        .split(',')
+        .filter_map(|tag| {
+            let tag = tag.trim();
+            let (weak, strong) = match tag.strip_prefix("W/") {
+                Some(strong) => (true, strong),
+                None => (false, tag),
+            };
+            let version = strong
+                .strip_prefix("\"v")
+                .and_then(|t| t.strip_suffix('"'))
+                .and_then(|v| v.parse::<i32>().ok())
+                .ok_or_else(|| ApiError::BadRequest(format!("Malformed If-Match tag `{}`", tag)));
+            match (weak, version) {
+                (true, Ok(_)) => None,
+                (_, version) => Some(version),
+            }
+        })
+        .collect::<Result<Vec<i32>, ApiError>>()
+        .map(Some)
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.headers().get_one("If-Match") {
+            None => Outcome::Success(IfMatch(None)),
+            Some(value) => match parse_if_match(value) {
+                Ok(versions) => Outcome::Success(IfMatch(versions)),
+                Err(e) => fail(req, e),
+            },
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use deadpool_postgres::Pool;
+use rocket::http::{ContentType, Header};
+use rocket::response::stream::ByteStream;
+use rocket::State;
+use rust_xlsxwriter::{Workbook, Worksheet};
+use serde_json::Value;
+
+use crate::auth::User;
+use crate::error::ApiError;
+use crate::filter::{Conditions, Paging, VehicleFilter};
+use crate::{db, search, Vehicle, VEHICLE_COLUMNS};
+
+/// Rows fetched from the cursor per round trip.
+const BATCH_ROWS: i32 = 500;
+
+#[derive(FromFormField, Clone, Copy)]
+pub enum Format {
+    #[field(value = "csv")]
+    Csv,
+    #[field(value = "jsonl")]
+    JsonLines,
+    #[field(value = "xlsx")]
+    Xlsx,
+}
+
+impl Format {
+    fn content_type(self) -> ContentType {
+        match self {
+            Format::Csv => ContentType::CSV,
+            Format::JsonLines => ContentType::new("application", "jsonl"),
+            Format::Xlsx => ContentType::new(
+                "application",
+                "vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            ),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::JsonLines => "jsonl",
+            Format::Xlsx => "xlsx",
+        }
+    }
+}
+
+/// A response the browser saves as `filename` instead of displaying.
+#[derive(Responder)]
+pub struct Download<R> {
+    inner: R,
+    content_type: ContentType,
+    disposition: Header<'static>,
+}
+
+impl<R> Download<R> {
+    pub fn new(inner: R, content_type: ContentType, filename: &str) -> Self {
+        Download {
+            inner,
+            content_type,
+            disposition: Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        }
+    }
+}
+
+/// One spreadsheet cell. Numbers keep their text so CSV shows prices as
+/// stored (`5000.00`); XLSX writes them as numbers.
+enum Cell {
+    Empty,
+    Text(String),
+    Number(String),
+    Bool(bool),
+}
+
+impl Cell {
+    fn text(&self) -> &str {
+        match self {
+            Cell::Empty => "",
+            Cell::Text(text) | Cell::Number(text) => text,
+            Cell::Bool(true) => "true",
+            Cell::Bool(false) => "false",
+        }
+    }
+}
+
+/// The columns of a CSV or XLSX export. They are named like the import
+/// fields so an export can be edited and imported again.
+fn header(spec_keys: &[String]) -> Vec<String> {
+    ["id", "vehicle_type", "manufacturer", "model", "price", "currency", "price_legacy"]
+        .iter()
+        .map(|column| column.to_string())
+        .chain(spec_keys.iter().map(|key| format!("spec.{}", key)))
+        .collect()
+}
+
+fn cells(vehicle: &Vehicle, spec_keys: &[String]) -> Vec<Cell> {
+    let text = |value: &Option<String>| match value {
+        Some(value) => Cell::Text(value.clone()),
+        None => Cell::Empty,
+    };
+    let mut cells = vec![
+        vehicle.id.map_or(Cell::Empty, |id| Cell::Number(id.to_string())),
+        Cell::Text(vehicle.vehicle_type.clone()),
+        Cell::Text(vehicle.manufacturer.clone()),
+        Cell::Text(vehicle.model.clone()),
+        vehicle
+            .price
+            .as_ref()
+            .map_or(Cell::Empty, |p| Cell::Number(p.amount.to_string())),
+        text(&vehicle.price.as_ref().map(|p| p.currency.clone())),
+        text(&vehicle.price_legacy),
+    ];
+    for key in spec_keys {
+        cells.push(match vehicle.data.get(key) {
+            None | Some(Value::Null) => Cell::Empty,
+            Some(Value::Number(n)) => Cell::Number(n.to_string()),
+            Some(Value::Bool(b)) => Cell::Bool(*b),
+            Some(Value::String(s)) => Cell::Text(s.clone()),
+            Some(other) => Cell::Text(other.to_string()),
+        });
+    }
+    cells
+}
+
+/// Turns rows into the bytes of one export format. CSV and JSON Lines are
+/// sent as each batch arrives. XLSX is a zip archive and can only be sent
+/// once complete: the constant-memory worksheet moves finished rows to a
+/// temporary file, but `save_to_buffer` then builds the whole compressed
+/// workbook in memory, so an XLSX export holds about its download size in
+/// memory at the end. Use CSV or JSON Lines for very large sets. Both steps
+/// do blocking work and run through `encode_rows` and `encode_finish`.
+enum Encoder {
+    Csv(Vec<String>),
+    JsonLines,
+    Xlsx {
+        workbook: Box<Workbook>,
+        sheet: Box<Worksheet>,
+        spec_keys: Vec<String>,
+        row: u32,
+    },
+}
+
+impl Encoder {
+    fn new(format: Format, spec_keys: Vec<String>) -> Result<Self, String> {
+        Ok(match format {
+            Format::Csv => Encoder::Csv(spec_keys),
+            Format::JsonLines => Encoder::JsonLines,
+            Format::Xlsx => {
+                let mut workbook = Workbook::new();
+                let mut sheet = workbook.new_worksheet_with_constant_memory();
+                sheet.set_name("Vehicles").map_err(|e| e.to_string())?;
+                sheet
+                    .write_row(0, 0, header(&spec_keys))
+                    .map_err(|e| e.to_string())?;
+                Encoder::Xlsx {
+                    workbook: Box::new(workbook),
+                    sheet: Box::new(sheet),
+                    spec_keys,
+                    row: 1,
+                }
+            }
+        })
+    }
+
+    fn header(&self) -> Vec<u8> {
+        match self {
+            Encoder::Csv(spec_keys) => csv_line(header(spec_keys).iter().map(String::as_str)),
+            _ => Vec::new(),
+        }
+    }
+
+    fn rows(&mut self, vehicles: &[Vehicle]) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        match self {
+            Encoder::Csv(spec_keys) => {
+                for vehicle in vehicles {
+                    let cells = cells(vehicle, spec_keys);
+                    out.extend(csv_line(cells.iter().map(Cell::text)));
+                }
+            }
+            Encoder::JsonLines => {
+                for vehicle in vehicles {
+                    serde_json::to_writer(&mut out, vehicle).map_err(|e| e.to_string())?;
+                    out.push(b'\n');
+                }
+            }
+            Encoder::Xlsx {
+                sheet,
+                spec_keys,
+                row,
+                ..
+            } => {
+                for vehicle in vehicles {
+                    for (col, cell) in cells(vehicle, spec_keys).iter().enumerate() {
+                        let col = col as u16;
+                        let written = match cell {
+                            Cell::Empty => continue,
+                            Cell::Text(text) => sheet.write_string(*row, col, text),
+                            Cell::Number(n) => match n.parse::<f64>() {
+                                Ok(n) => sheet.write_number(*row, col, n),
+                                Err(_) => sheet.write_string(*row, col, n),
+                            },
+                            Cell::Bool(b) => sheet.write_boolean(*row, col, *b),
+                        };
+                        written.map_err(|e| e.to_string())?;
+                    }
+                    *row += 1;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    fn finish(self) -> Result<Vec<u8>, String> {
+        match self {
+            Encoder::Xlsx {
+                mut workbook,
+                sheet,
+                ..
+            } => {
+                workbook.push_worksheet(*sheet);
+                workbook.save_to_buffer().map_err(|e| e.to_string())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Encodes a batch, moving XLSX work, which writes a temporary file, to the
+/// blocking pool.
+async fn encode_rows(
+    mut encoder: Encoder,
+    vehicles: Vec<Vehicle>,
+) -> Result<(Encoder, Vec<u8>), String> {
+    if !matches!(encoder, Encoder::Xlsx { .. }) {
+        let chunk = encoder.rows(&vehicles)?;
+        return Ok((encoder, chunk));
+    }
+    tokio::task::spawn_blocking(move || {
+        let chunk = encoder.rows(&vehicles)?;
+        Ok((encoder, chunk))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Finishes the export; compressing an XLSX workbook runs on the blocking
+/// pool.
+async fn encode_finish(encoder: Encoder) -> Result<Vec<u8>, String> {
+    if !matches!(encoder, Encoder::Xlsx { .. }) {
+        return encoder.finish();
+    }
+    tokio::task::spawn_blocking(move || encoder.finish())
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn csv_line<'a>(fields: impl Iterator<Item = &'a str>) -> Vec<u8> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    // Writing to a Vec can't fail.
+    writer.write_record(fields).unwrap();
+    writer.into_inner().unwrap_or_default()
+}
+
+/// Exports every vehicle matching the listing's `sort` and filters, and
+/// optionally a full-text query `q` or the fuzzy search's `fuzzy` and
+/// `min_similarity`, as CSV, JSON Lines or XLSX. Rows are read through a
+/// cursor. CSV and JSON Lines are streamed as they are read; an XLSX file is
+/// only sent once complete, see `Encoder`.
+#[allow(clippy::too_many_arguments)]
+#[get("/api/vehicles/export?<format>&<sort>&<q>&<fuzzy>&<min_similarity>&<filter..>")]
+pub async fn export_vehicles(
+    pool: &State<Pool>,
+    _user: User,
+    format: Format,
+    sort: Option<&str>,
+    q: Option<&str>,
+    fuzzy: Option<&str>,
+    min_similarity: Option<f32>,
+    filter: VehicleFilter,
+) -> Result<Download<ByteStream![Vec<u8>]>, ApiError> {
+    let order_by = Paging::new(None, None, sort)?.order_sql();
+    let mut conditions = Conditions::default();
+    conditions.push("deleted_at IS NULL".to_string());
+    filter.apply(&mut conditions)?;
+    if let Some(q) = q {
+        search::text_search_condition(q, &mut conditions)?;
+    }
+    if let Some(fuzzy) = fuzzy {
+        search::fuzzy_search_condition(fuzzy, min_similarity, &mut conditions)?;
+    }
+
+    let client = db::client(pool).await?;
+    let spec_keys = match format {
+        Format::JsonLines => Vec::new(),
+        _ => client
+            .query(
+                &format!(
+                    "SELECT DISTINCT key FROM vehicles, jsonb_object_keys(data) AS key {} ORDER BY key",
+                    conditions.where_clause()
+                ),
+                &conditions.params(),
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect::<Vec<String>>(),
+    };
+    let statement = client
+        .prepare(&format!(
+            "SELECT {} FROM vehicles {} {}",
+            VEHICLE_COLUMNS,
+            conditions.where_clause(),
+            order_by
+        ))
+        .await?;
+    let encoder = Encoder::new(format, spec_keys).map_err(ApiError::Internal)?;
+
+    // Failures past this point can't change the status any more; they end
+    // the download early and are logged.
+    let stream = ByteStream! {
+        let mut client = client;
+        let transaction = match client.transaction().await {
+            Ok(transaction) => transaction,
+            Err(e) => return error!("Export failed: {}", e),
+        };
+        let portal = match transaction.bind(&statement, &conditions.params()).await {
+            Ok(portal) => portal,
+            Err(e) => return error!("Export failed: {}", e),
+        };
+        yield encoder.header();
+        let mut encoder = encoder;
+        loop {
+            let rows = match transaction.query_portal(&portal, BATCH_ROWS).await {
+                Ok(rows) => rows,
+                Err(e) => return error!("Export failed: {}", e),
+            };
+            if rows.is_empty() {
+                break;
+            }
+            let vehicles = rows.iter().map(Vehicle::from_row).collect::<Vec<Vehicle>>();
+            let chunk = match encode_rows(encoder, vehicles).await {
+                Ok((next, chunk)) => {
+                    encoder = next;
+                    chunk
+                }
+                Err(e) => return error!("Export failed: {}", e),
+            };
+            if !chunk.is_empty() {
+                yield chunk;
+            }
+        }
+        match encode_finish(encoder).await {
+            Ok(chunk) => yield chunk,
+            Err(e) => error!("Export failed: {}", e),
+        }
+    };
+
+    Ok(Download::new(
+        stream,
+        format.content_type(),
+        &format!("vehicles.{}", format.extension()),
+    ))
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use tokio_postgres::types::ToSql;
+
+use crate::error::ApiError;
+
+/// Collects `WHERE` conditions and their bind parameters so handlers can
+/// build queries from optional request filters without string-pasting values.
+#[derive(Default)]
+pub struct Conditions {
+    clauses: Vec<String>,
+    params: Vec<Box<dyn ToSql + Sync + Send>>,
+}
+
+impl Conditions {
+    /// Registers a bind parameter and returns its `$n` placeholder.
+    pub fn param<T: ToSql + Sync + Send + 'static>(&mut self, value: T) -> String {
+        self.params.push(Box::new(value));
+        format!("${}", self.params.len())
+    }
+
+    pub fn push(&mut self, clause: String) {
+        self.clauses.push(clause);
+    }
+
+    pub fn where_clause(&self) -> String {
+        match self.clauses.is_empty() {
+            true => String::new(),
+            false => format!("WHERE {}", self.clauses.join(" AND ")),
+        }
+    }
+
+    pub fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.params
+            .iter()
+            .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+            .collect()
+    }
+}
+
+/// Query-string filters accepted by the vehicle listing.
+///
+/// `vehicle_type`, `manufacturer`, `model` and `currency` match exactly,
+/// `price_min`/`price_max` bound the price amount. Specifications are
+/// filtered with `spec.<key>=<value>` for equality and `spec.<key>>=<n>`,
+/// `spec.<key><=<n>`, `spec.<key>><n>`, `spec.<key><<n>` for numeric ranges.
+#[derive(FromForm, Default)]
+pub struct VehicleFilter {
+    pub vehicle_type: Option<String>,
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    pub currency: Option<String>,
+    pub price_min: Option<String>,
+    pub price_max: Option<String>,
+    pub spec: HashMap<String, String>,
+}
+
+impl VehicleFilter {
+    pub fn is_empty(&self) -> bool {
+        self.vehicle_type.is_none()
+            && self.manufacturer.is_none()
+            && self.model.is_none()
+            && self.currency.is_none()
+            && self.price_min.is_none()
+            && self.price_max.is_none()
+            && self.spec.is_empty()
+    }
+
+    pub fn apply(&self, conditions: &mut Conditions) -> Result<(), ApiError> {
+        for (column, value) in [
+            ("vehicle_type", &self.vehicle_type),
+            ("manufacturer", &self.manufacturer),
+            ("model", &self.model),
+            ("price_currency", &self.currency),
+        ] {
+            if let Some(value) = value {
+                let value = conditions.param(value.clone());
+                conditions.push(format!("{} = {}", column, value));
+            }
+        }
+        for (op, bound) in [(">=", &self.price_min), ("<=", &self.price_max)] {
+            if let Some(bound) = bound {
+                let bound = parse_number(bound)?;
+                let bound = conditions.param(bound);
+                conditions.push(format!("price_amount {} {}", op, bound));
+            }
+        }
+        for (key, value) in &self.spec {
+            let (key, op, value) = parse_spec_filter(key, value)?;
+            let key = conditions.param(key.to_string());
+            match op {
+                "=" => {
+                    let value = conditions.param(value.to_string());
+                    conditions.push(format!("data->>{}::TEXT = {}::TEXT", key, value));
+                }
+                op => {
+                    let number = conditions.param(parse_number(value)?);
+                    conditions.push(format!(
+                        "CASE WHEN jsonb_typeof(data->{key}::TEXT) = 'number' \
+                         THEN (data->>{key}::TEXT)::NUMERIC {op} {number} ELSE false END"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_number(value: &str) -> Result<Decimal, ApiError> {
+    value
+        .parse::<Decimal>()
+        .map_err(|_| ApiError::BadRequest(format!("`{}` is not a number", value)))
+}
+
+/// Splits a `spec` map entry into key, operator and value. Rocket hands us
+/// `spec.year>=2018` as key `year>` with value `2018`, and `spec.year>2018`
+/// as key `year>2018` with an empty value.
+fn parse_spec_filter<'a>(key: &'a str, value: &'a str) -> Result<(&'a str, &'static str, &'a str), ApiError> {
+    let (key, op, value) = if let Some(key) = key.strip_suffix('>') {
+        (key, ">=", value)
+    } else if let Some(key) = key.strip_suffix('<') {
+        (key, "<=", value)
+    } else if let Some((key, bound)) = key.split_once('>') {
+        (key, ">", bound)
+    } else if let Some((key, bound)) = key.split_once('<') {
+        (key, "<", bound)
+    } else {
+        (key, "=", value)
+    };
+    if !is_spec_key(key) {
+        return Err(ApiError::BadRequest(format!(
+            "`{}` is not a valid specification key",
+            key
+        )));
+    }
+    Ok((key, op, value))
+}
+
+/// Specification keys are limited to ASCII letters, digits, `_` and `-` so
+/// they are safe in query strings and CSV headers alike.
+pub fn is_spec_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+/// `limit`/`offset` paging and `sort=field:asc|desc[,field:asc|desc...]`
+/// ordering of a listing.
+pub struct Paging {
+    pub limit: i64,
+    pub offset: i64,
+    order_by: String,
+}
+
+impl Paging {
+    pub fn new(limit: Option<i64>, offset: Option<i64>, sort: Option<&str>) -> Result<Self, ApiError> {
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(ApiError::BadRequest(format!(
+                "limit must be between 1 and {}",
+                MAX_PAGE_SIZE
+            )));
+        }
+        let offset = offset.unwrap_or(0);
+        if offset < 0 {
+            return Err(ApiError::BadRequest("offset can't be negative".to_string()));
+        }
+
+        let mut order_by = Vec::new();
+        for part in sort.unwrap_or("").split(',').filter(|p| !p.is_empty()) {
+            let (field, direction) = part.split_once(':').unwrap_or((part, "asc"));
+            let column = match field {
+                "id" | "vehicle_type" | "manufacturer" | "model" => field,
+                "price" => "price_amount",
+                _ => {
+                    return Err(ApiError::BadRequest(format!(
+                        "Can't sort by `{}`",
+                        field
+                    )))
+                }
+            };
+            let direction = match direction {
+                "asc" => "ASC NULLS LAST",
+                "desc" => "DESC NULLS LAST",
+                _ => {
+                    return Err(ApiError::BadRequest(format!(
+                        "Sort direction must be asc or desc, got `{}`",
+                        direction
+                    )))
+                }
+            };
+            order_by.push(format!("{} {}", column, direction));
+        }
+        // Tie-break on the primary key so pages don't overlap.
+        order_by.push("id ASC".to_string());
+
+        Ok(Paging {
+            limit,
+            offset,
+            order_by: order_by.join(", "),
+        })
+    }
+
+    pub fn sql(&self) -> String {
+        format!("{} LIMIT {} OFFSET {}", self.order_sql(), self.limit, self.offset)
+    }
+
+    /// The `ORDER BY` clause alone, for queries that aren't paged.
+    pub fn order_sql(&self) -> String {
+        format!("ORDER BY {}", self.order_by)
+    }
+
+    /// Offset of the following page, if there is one.
+    pub fn next_offset(&self, total: i64) -> Option<i64> {
+        Some(self.offset + self.limit).filter(|next| *next < total)
+    }
+}
+
+impl Default for Paging {
+    fn default() -> Self {
+        Paging::new(None, None, None).unwrap()
+    }
+}
@@ -0,0 +1,66 @@
+use deadpool_postgres::Pool;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+use serde_json::Value;
+
+use crate::auth::User;
+use crate::db;
+use crate::error::ApiError;
+
+/// One recorded change to a vehicle. `changes` maps each changed field
+/// (`model`, `price.amount`, `spec.<key>`, ...) to its `before` and `after`
+/// values.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct HistoryEntry {
+    pub id: i64,
+    /// `create`, `update`, `delete`, `restore` or `purge`.
+    pub action: String,
+    pub changes: Value,
+    pub actor: Option<String>,
+    pub changed_at: String,
+}
+
+/// Every change recorded for a vehicle, oldest first. Deleted and purged
+/// vehicles keep their history.
+// Ranked below `/api/vehicles/search/<query>`, which also matches
+// `/api/vehicles/search/history`.
+#[get("/api/vehicles/<id>/history", rank = 2)]
+pub async fn get_vehicle_history(
+    pool: &State<Pool>,
+    _user: User,
+    id: i32,
+) -> Result<Json<Vec<HistoryEntry>>, ApiError> {
+    let client = db::client(pool).await?;
+    let entries = client
+        .query(
+            "SELECT id, action, changes, actor, changed_at::TEXT FROM vehicle_history
+            WHERE vehicle_id = $1
+            ORDER BY changed_at ASC, id ASC",
+            &[&id],
+        )
+        .await?
+        .iter()
+        .map(|row| HistoryEntry {
+            id: row.get(0),
+            action: row.get(1),
+            changes: row.get(2),
+            actor: row.get(3),
+            changed_at: row.get(4),
+        })
+        .collect::<Vec<HistoryEntry>>();
+    if entries.is_empty() {
+        // Vehicles created before the history existed have no entries yet.
+        let exists: bool = client
+            .query_one("SELECT EXISTS(SELECT * FROM vehicles WHERE id = $1)", &[&id])
+            .await?
+            .get(0);
+        if !exists {
+            return Err(ApiError::NotFound(
+                "Vehicle with ID not found in the database".to_string(),
+            ));
+        }
+    }
+    Ok(Json(entries))
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+
+use deadpool_postgres::Pool;
+use rocket::data::{Data, Limits, ToByteUnit};
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+use rust_decimal::Decimal;
+use serde_json::{Map, Value};
+
+use crate::auth::Editor;
+use crate::{db, validate_required};
+use crate::error::ApiError;
+use crate::filter::is_spec_key;
+use crate::price::Price;
+
+/// Body size used when Rocket's `limits.csv` isn't configured.
+const DEFAULT_CSV_LIMIT_MIB: u64 = 2;
+
+/// Query string of `POST /api/vehicles/import`.
+///
+/// Columns named like a vehicle field (`vehicle_type`, `manufacturer`,
+/// `model`, `price`, `currency`) or `spec.<key>` are picked up as is; any
+/// other header is mapped with `map.<header>=<field>`, or skipped with an
+/// empty field.
+#[derive(FromForm, Default)]
+pub struct ImportOptions {
+    /// Validate and report without inserting anything.
+    pub dry_run: bool,
+    /// Currency of rows that don't have a currency column.
+    pub currency: Option<String>,
+    pub map: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq)]
+enum Field {
+    VehicleType,
+    Manufacturer,
+    Model,
+    Price,
+    Currency,
+    Spec(String),
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        match name {
+            "vehicle_type" => Some(Field::VehicleType),
+            "manufacturer" => Some(Field::Manufacturer),
+            "model" => Some(Field::Model),
+            "price" => Some(Field::Price),
+            "currency" => Some(Field::Currency),
+            _ => name
+                .strip_prefix("spec.")
+                .filter(|key| is_spec_key(key))
+                .map(|key| Field::Spec(key.to_string())),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RowReport {
+    /// Line of the row in the CSV; the header is line 1.
+    pub row: u64,
+    /// `accepted` or `rejected`.
+    pub status: &'static str,
+    /// ID of the inserted vehicle; absent on dry runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub accepted: usize,
+    pub rejected: usize,
+    /// CSV headers that weren't mapped to any field.
+    pub ignored_columns: Vec<String>,
+    pub rows: Vec<RowReport>,
+}
+
+impl ImportReport {
+    fn accept(&mut self, row: u64, id: Option<i32>) {
+        self.accepted += 1;
+        self.rows.push(RowReport {
+            row,
+            status: "accepted",
+            id,
+            reasons: Vec::new(),
+        });
+    }
+
+    fn reject(&mut self, row: u64, reasons: Vec<String>) {
+        self.rejected += 1;
+        self.rows.push(RowReport {
+            row,
+            status: "rejected",
+            id: None,
+            reasons,
+        });
+    }
+}
+
+/// A validated row, ready to insert.
+struct NewVehicle {
+    vehicle_type: String,
+    manufacturer: String,
+    model: String,
+    price: Price,
+    data: Value,
+}
+
+/// Bulk-creates vehicles from a CSV with a header row. Every row is
+/// validated with the same rules as `add_vehicle` and reported on; the valid
+/// ones are inserted in one transaction. A dry run stops after validating,
+/// so it inserts nothing and uses up no vehicle ids. A row the database
+/// refuses is rejected on its own, the other rows are kept.
+#[post("/api/vehicles/import?<options..>", data = "<csv>")]
+pub async fn import_vehicles(
+    pool: &State<Pool>,
+    user: Editor,
+    limits: &Limits,
+    options: ImportOptions,
+    csv: Data<'_>,
+) -> Result<Json<ImportReport>, ApiError> {
+    let limit = limits
+        .get("csv")
+        .unwrap_or(DEFAULT_CSV_LIMIT_MIB.mebibytes());
+    let csv = csv
+        .open(limit)
+        .into_string()
+        .await
+        .map_err(|_| ApiError::BadRequest("CSV must be UTF-8 text".to_string()))?;
+    if !csv.is_complete() {
+        return Err(ApiError::BadRequest(format!(
+            "CSV is larger than the {} limit",
+            limit
+        )));
+    }
+    let csv = csv.into_inner();
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(csv.trim_start_matches('\u{feff}').as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| ApiError::BadRequest(format!("Can't read the CSV header: {}", e)))?
+        .iter()
+        .map(str::to_string)
+        .collect::<Vec<String>>();
+    let (columns, ignored_columns) = map_columns(&headers, &options)?;
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        // Fall back to counting rows if the reader can't tell the line.
+        let line = record
+            .as_ref()
+            .ok()
+            .and_then(|record| record.position())
+            .map(|position| position.line())
+            .unwrap_or(index as u64 + 2);
+        let vehicle = match record {
+            Ok(record) if record.len() != headers.len() => Err(vec![format!(
+                "Expected {} columns, found {}",
+                headers.len(),
+                record.len()
+            )]),
+            Ok(record) => parse_row(&columns, &record, options.currency.as_deref()),
+            Err(e) => Err(vec![format!("Unreadable row: {}", e)]),
+        };
+        rows.push((line, vehicle));
+    }
+
+    let mut report = ImportReport {
+        dry_run: options.dry_run,
+        accepted: 0,
+        rejected: 0,
+        ignored_columns,
+        rows: Vec::new(),
+    };
+    if options.dry_run {
+        for (row, vehicle) in rows {
+            match vehicle {
+                Ok(_) => report.accept(row, None),
+                Err(reasons) => report.reject(row, reasons),
+            }
+        }
+        return Ok(Json(report));
+    }
+
+    let mut client = db::client(pool).await?;
+    let mut transaction = db::audited(&mut client, &user).await?;
+    let insert = transaction
+        .prepare(
+            "INSERT INTO vehicles (vehicle_type, manufacturer, model, price_amount, price_currency, data) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+        )
+        .await?;
+    for (row, vehicle) in rows {
+        let v = match vehicle {
+            Ok(v) => v,
+            Err(reasons) => {
+                report.reject(row, reasons);
+                continue;
+            }
+        };
+        // A failed statement aborts the whole transaction; the savepoint
+        // confines the failure to this row.
+        let savepoint = transaction.savepoint("import_row").await?;
+        let inserted = savepoint
+            .query_one(
+                &insert,
+                &[&v.vehicle_type, &v.manufacturer, &v.model, &v.price.amount, &v.price.currency, &v.data],
+            )
+            .await;
+        match inserted.map_err(ApiError::from) {
+            Ok(inserted) => {
+                savepoint.commit().await?;
+                report.accept(row, Some(inserted.get(0)));
+            }
+            Err(ApiError::Validation(reason) | ApiError::Conflict(reason)) => {
+                savepoint.rollback().await?;
+                report.reject(row, vec![reason]);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    transaction.commit().await?;
+    Ok(Json(report))
+}
+
+/// Resolves every CSV column to the field it fills, and returns the headers
+/// that fill none.
+fn map_columns(
+    headers: &[String],
+    options: &ImportOptions,
+) -> Result<(Vec<Option<Field>>, Vec<String>), ApiError> {
+    for header in options.map.keys() {
+        if !headers.contains(header) {
+            return Err(ApiError::BadRequest(format!(
+                "`{}` is not a column of the CSV",
+                header
+            )));
+        }
+    }
+
+    let mut columns = Vec::new();
+    let mut ignored = Vec::new();
+    for header in headers {
+        let field = match options.map.get(header) {
+            Some(target) if target.is_empty() => None,
+            Some(target) => Some(Field::parse(target).ok_or_else(|| {
+                ApiError::BadRequest(format!("`{}` is not a vehicle field", target))
+            })?),
+            None => Field::parse(&header.to_lowercase()),
+        };
+        if field.is_some() && columns.contains(&field) {
+            return Err(ApiError::BadRequest(format!(
+                "More than one column maps to the field of `{}`",
+                header
+            )));
+        }
+        if field.is_none() {
+            ignored.push(header.clone());
+        }
+        columns.push(field);
+    }
+
+    let mut missing = [
+        (Field::VehicleType, "vehicle_type"),
+        (Field::Manufacturer, "manufacturer"),
+        (Field::Model, "model"),
+        (Field::Price, "price"),
+        (Field::Currency, "currency"),
+    ]
+    .into_iter()
+    .filter(|(field, _)| !columns.contains(&Some(field.clone())))
+    .map(|(_, name)| name)
+    .collect::<Vec<&str>>();
+    if options.currency.is_some() {
+        missing.retain(|name| *name != "currency");
+    }
+    if !missing.is_empty() {
+        return Err(ApiError::BadRequest(format!(
+            "No column for {}",
+            missing.join(", ")
+        )));
+    }
+    if let Some(currency) = &options.currency {
+        Price {
+            amount: Decimal::ZERO,
+            currency: currency.clone(),
+        }
+        .validate()?;
+    }
+    Ok((columns, ignored))
+}
+
+/// Builds a vehicle from one CSV record, collecting every problem with it
+/// rather than stopping at the first.
+fn parse_row(
+    columns: &[Option<Field>],
+    record: &csv::StringRecord,
+    default_currency: Option<&str>,
+) -> Result<NewVehicle, Vec<String>> {
+    let mut reasons = Vec::new();
+    let mut vehicle_type = String::new();
+    let mut manufacturer = String::new();
+    let mut model = String::new();
+    let mut price_text = "";
+    let mut currency = default_currency.map(str::to_string);
+    let mut data = Map::new();
+
+    for (field, value) in columns.iter().zip(record.iter()) {
+        match field {
+            None => {}
+            Some(Field::VehicleType) => vehicle_type = value.to_string(),
+            Some(Field::Manufacturer) => manufacturer = value.to_string(),
+            Some(Field::Model) => model = value.to_string(),
+            Some(Field::Price) => price_text = value,
+            Some(Field::Currency) if value.is_empty() => {}
+            Some(Field::Currency) => currency = Some(value.to_uppercase()),
+            Some(Field::Spec(_)) if value.is_empty() => {}
+            Some(Field::Spec(key)) => {
+                // Numbers and booleans keep their JSON type so range filters
+                // work on them.
+                let value = match serde_json::from_str::<Value>(value) {
+                    Ok(v @ (Value::Number(_) | Value::Bool(_))) => v,
+                    _ => Value::String(value.to_string()),
+                };
+                data.insert(key.clone(), value);
+            }
+        }
+    }
+
+    for (name, value) in [
+        ("vehicle_type", &vehicle_type),
+        ("manufacturer", &manufacturer),
+        ("model", &model),
+    ] {
+        if let Err(e) = validate_required(name, value) {
+            reasons.push(e.message().to_string());
+        }
+    }
+    let amount = match price_text {
+        "" => {
+            reasons.push("Price is missing".to_string());
+            None
+        }
+        text => match text.parse::<Decimal>() {
+            Ok(amount) => Some(amount),
+            Err(_) => {
+                reasons.push(format!("Price `{}` is not a number", text));
+                None
+            }
+        },
+    };
+    let price = match (amount, currency) {
+        (Some(amount), Some(currency)) => {
+            let price = Price { amount, currency };
+            if let Err(e) = price.validate() {
+                reasons.push(e.message().to_string());
+            }
+            Some(price)
+        }
+        (Some(_), None) => {
+            reasons.push("Currency is missing".to_string());
+            None
+        }
+        (None, Some(currency)) => {
+            // Still check the currency so the report lists every problem.
+            let check = Price {
+                amount: Decimal::ZERO,
+                currency,
+            };
+            if let Err(e) = check.validate() {
+                reasons.push(e.message().to_string());
+            }
+            None
+        }
+        (None, None) => None,
+    };
+
+    match (price, reasons.is_empty()) {
+        (Some(price), true) => Ok(NewVehicle {
+            vehicle_type,
+            manufacturer,
+            model,
+            price,
+            data: Value::Object(data),
+        }),
+        _ => Err(reasons),
+    }
+}
@@ -0,0 +1,415 @@
+use deadpool_postgres::Pool;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str};
+use rocket::http::ContentType;
+use rocket::State;
+use tokio_postgres::Row;
+
+use crate::auth::User;
+use crate::error::ApiError;
+use crate::export::Download;
+use crate::filter::{Conditions, Paging, VehicleFilter};
+use crate::qr_builder::{self, QrConfig, QrEcLevel};
+use crate::{db, search, Vehicle, VEHICLE_COLUMNS};
+
+/// Most labels one request may print; the PDF is built in memory.
+const MAX_LABELS: i64 = 2000;
+const MAX_GRID: u32 = 20;
+/// Smallest label side that still fits a scannable code and its text.
+const MIN_LABEL_MM: f32 = 20.0;
+/// Blank space kept inside each label edge.
+const PADDING_MM: f32 = 2.0;
+/// Blank modules around the code; the label padding adds to it.
+const QUIET_ZONE: u32 = 2;
+const FONT_SIZE: f32 = 7.0;
+const LINE_HEIGHT: f32 = FONT_SIZE * 1.2;
+/// Courier is monospaced, so text can be centered and cut to fit without
+/// font metrics: every glyph is 600/1000 of the font size wide.
+const CHAR_WIDTH: f32 = FONT_SIZE * 0.6;
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+
+#[derive(FromFormField, Clone, Copy)]
+pub enum PageSize {
+    #[field(value = "a4")]
+    A4,
+    #[field(value = "letter")]
+    Letter,
+}
+
+impl PageSize {
+    /// Width and height in millimetres.
+    fn size_mm(self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (210.0, 297.0),
+            PageSize::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+/// Sheet layout of `GET /api/vehicles/labels`, given as `sheet.<field>`;
+/// lengths are in millimetres. Labels fill the page between the margins,
+/// `gap_x`/`gap_y` apart. The defaults match the common 3 × 7 A4 sheets of
+/// 63.5 × 38.1 mm labels.
+#[derive(FromForm)]
+pub struct LabelLayout {
+    #[field(default = PageSize::A4)]
+    pub page: PageSize,
+    #[field(default = 7)]
+    pub rows: u32,
+    #[field(default = 3)]
+    pub columns: u32,
+    /// Left and right page margin.
+    #[field(default = 7.2)]
+    pub margin_x: f32,
+    /// Top and bottom page margin.
+    #[field(default = 15.1)]
+    pub margin_y: f32,
+    /// Space between columns.
+    #[field(default = 2.5)]
+    pub gap_x: f32,
+    /// Space between rows.
+    #[field(default = 0.0)]
+    pub gap_y: f32,
+}
+
+/// Where labels go on a page, in points from the bottom left corner.
+struct Sheet {
+    page_width: f32,
+    page_height: f32,
+    rows: u32,
+    columns: u32,
+    margin_x: f32,
+    margin_y: f32,
+    gap_x: f32,
+    gap_y: f32,
+    label_width: f32,
+    label_height: f32,
+}
+
+impl LabelLayout {
+    fn sheet(&self) -> Result<Sheet, ApiError> {
+        for (name, count) in [("rows", self.rows), ("columns", self.columns)] {
+            if !(1..=MAX_GRID).contains(&count) {
+                return Err(ApiError::BadRequest(format!(
+                    "{} must be between 1 and {}",
+                    name, MAX_GRID
+                )));
+            }
+        }
+        for (name, length) in [
+            ("margin_x", self.margin_x),
+            ("margin_y", self.margin_y),
+            ("gap_x", self.gap_x),
+            ("gap_y", self.gap_y),
+        ] {
+            if !length.is_finite() || length < 0.0 {
+                return Err(ApiError::BadRequest(format!(
+                    "{} must be a length of 0 mm or more",
+                    name
+                )));
+            }
+        }
+        let (page_width, page_height) = self.page.size_mm();
+        let (rows, columns) = (self.rows as f32, self.columns as f32);
+        let label_width = (page_width - 2.0 * self.margin_x - (columns - 1.0) * self.gap_x) / columns;
+        let label_height = (page_height - 2.0 * self.margin_y - (rows - 1.0) * self.gap_y) / rows;
+        if label_width < MIN_LABEL_MM || label_height < MIN_LABEL_MM {
+            return Err(ApiError::BadRequest(format!(
+                "Labels would be {:.1} × {:.1} mm; with these margins and gaps, at least {} mm per side needs fewer rows or columns",
+                label_width, label_height, MIN_LABEL_MM
+            )));
+        }
+        Ok(Sheet {
+            page_width: page_width * POINTS_PER_MM,
+            page_height: page_height * POINTS_PER_MM,
+            rows: self.rows,
+            columns: self.columns,
+            margin_x: self.margin_x * POINTS_PER_MM,
+            margin_y: self.margin_y * POINTS_PER_MM,
+            gap_x: self.gap_x * POINTS_PER_MM,
+            gap_y: self.gap_y * POINTS_PER_MM,
+            label_width: label_width * POINTS_PER_MM,
+            label_height: label_height * POINTS_PER_MM,
+        })
+    }
+}
+
+impl Sheet {
+    fn per_page(&self) -> usize {
+        (self.rows * self.columns) as usize
+    }
+
+    /// Bottom left corner of the `index`th label on its page, filling rows
+    /// left to right from the top.
+    fn origin(&self, index: usize) -> (f32, f32) {
+        let row = (index / self.columns as usize) as f32;
+        let column = (index % self.columns as usize) as f32;
+        let x = self.margin_x + column * (self.label_width + self.gap_x);
+        let top = self.page_height - self.margin_y - row * (self.label_height + self.gap_y);
+        (x, top - self.label_height)
+    }
+}
+
+/// A vehicle to print, with the public slug its code encodes.
+struct Label {
+    vehicle: Vehicle,
+    slug: Option<String>,
+}
+
+impl Label {
+    /// Reads a row of `VEHICLE_COLUMNS, public_slug`.
+    fn from_row(row: &Row) -> Self {
+        Label {
+            vehicle: Vehicle::from_row(row),
+            slug: row.get(9),
+        }
+    }
+
+    fn id(&self) -> i32 {
+        self.vehicle.id.unwrap_or_default()
+    }
+}
+
+/// Prints QR labels for vehicles as a PDF sized for label sheets. Pick the
+/// vehicles either with `ids`, a comma-separated list printed in that order,
+/// or with the listing's `sort` and filters plus an optional full-text `q` or
+/// the fuzzy search's `fuzzy` and `min_similarity`, as for exports. Each
+/// label shows the code with the manufacturer, model and id beneath it.
+#[allow(clippy::too_many_arguments)]
+#[get("/api/vehicles/labels?<ids>&<sort>&<q>&<fuzzy>&<min_similarity>&<sheet>&<filter..>")]
+pub async fn vehicle_labels(
+    pool: &State<Pool>,
+    _user: User,
+    qr: &State<QrConfig>,
+    ids: Option<&str>,
+    sort: Option<&str>,
+    q: Option<&str>,
+    fuzzy: Option<&str>,
+    min_similarity: Option<f32>,
+    sheet: LabelLayout,
+    filter: VehicleFilter,
+) -> Result<Download<Vec<u8>>, ApiError> {
+    let sheet = sheet.sheet()?;
+    let client = db::client(pool).await?;
+    let labels = match ids {
+        Some(ids) => {
+            if sort.is_some() || q.is_some() || fuzzy.is_some() || !filter.is_empty() {
+                return Err(ApiError::BadRequest(
+                    "ids can't be combined with sort, q, fuzzy or filters".to_string(),
+                ));
+            }
+            let ids = parse_ids(ids)?;
+            let found = client
+                .query(
+                    &format!(
+                        "SELECT {}, public_slug FROM vehicles WHERE id = ANY($1) AND deleted_at IS NULL",
+                        VEHICLE_COLUMNS
+                    ),
+                    &[&ids],
+                )
+                .await?
+                .iter()
+                .map(Label::from_row)
+                .collect::<Vec<Label>>();
+            let missing = ids
+                .iter()
+                .filter(|id| !found.iter().any(|label| label.id() == **id))
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>();
+            if !missing.is_empty() {
+                return Err(ApiError::NotFound(format!(
+                    "No vehicle with ID {}",
+                    missing.join(", ")
+                )));
+            }
+            let mut found = found;
+            found.sort_by_key(|label| ids.iter().position(|id| *id == label.id()));
+            found
+        }
+        None => {
+            let order_by = Paging::new(None, None, sort)?.order_sql();
+            let mut conditions = Conditions::default();
+            conditions.push("deleted_at IS NULL".to_string());
+            filter.apply(&mut conditions)?;
+            if let Some(q) = q {
+                search::text_search_condition(q, &mut conditions)?;
+            }
+            if let Some(fuzzy) = fuzzy {
+                search::fuzzy_search_condition(fuzzy, min_similarity, &mut conditions)?;
+            }
+            let limit = conditions.param(MAX_LABELS + 1);
+            client
+                .query(
+                    &format!(
+                        "SELECT {}, public_slug FROM vehicles {} {} LIMIT {}",
+                        VEHICLE_COLUMNS,
+                        conditions.where_clause(),
+                        order_by,
+                        limit
+                    ),
+                    &conditions.params(),
+                )
+                .await?
+                .iter()
+                .map(Label::from_row)
+                .collect::<Vec<Label>>()
+        }
+    };
+    if labels.is_empty() {
+        return Err(ApiError::NotFound("No vehicles to label".to_string()));
+    }
+    if labels.len() as i64 > MAX_LABELS {
+        return Err(ApiError::BadRequest(format!(
+            "At most {} labels can be printed at once; narrow the filters",
+            MAX_LABELS
+        )));
+    }
+
+    let revoked = labels
+        .iter()
+        .filter(|label| label.slug.is_none())
+        .map(|label| label.id().to_string())
+        .collect::<Vec<String>>();
+    if !revoked.is_empty() {
+        return Err(ApiError::Conflict(format!(
+            "The QR code of vehicle {} was revoked; generate a new one first",
+            revoked.join(", ")
+        )));
+    }
+
+    // Encoding up to MAX_LABELS codes takes long enough to stall the other
+    // requests on this worker.
+    let qr = QrConfig::clone(qr);
+    let pdf = tokio::task::spawn_blocking(move || render_sheets(&sheet, &labels, &qr))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok(Download::new(pdf, ContentType::PDF, "vehicle-labels.pdf"))
+}
+
+fn parse_ids(ids: &str) -> Result<Vec<i32>, ApiError> {
+    let mut parsed = Vec::new();
+    for id in ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        let id = id
+            .parse::<i32>()
+            .map_err(|_| ApiError::BadRequest(format!("`{}` is not a vehicle ID", id)))?;
+        if !parsed.contains(&id) {
+            parsed.push(id);
+        }
+    }
+    if parsed.is_empty() {
+        return Err(ApiError::BadRequest("ids is empty".to_string()));
+    }
+    if parsed.len() as i64 > MAX_LABELS {
+        return Err(ApiError::BadRequest(format!(
+            "At most {} labels can be printed at once",
+            MAX_LABELS
+        )));
+    }
+    Ok(parsed)
+}
+
+fn render_sheets(sheet: &Sheet, labels: &[Label], qr: &QrConfig) -> Result<Vec<u8>, ApiError> {
+    let mut pdf = Pdf::new();
+    let mut next_ref = Ref::new(1);
+    let catalog_id = next_ref.bump();
+    let pages_id = next_ref.bump();
+    let font_id = next_ref.bump();
+    let font_name = Name(b"F1");
+
+    let pages = labels.chunks(sheet.per_page()).collect::<Vec<&[Label]>>();
+    let page_ids = pages
+        .iter()
+        .map(|_| (next_ref.bump(), next_ref.bump()))
+        .collect::<Vec<(Ref, Ref)>>();
+
+    pdf.catalog(catalog_id).pages(pages_id);
+    pdf.pages(pages_id)
+        .kids(page_ids.iter().map(|(page_id, _)| *page_id))
+        .count(page_ids.len() as i32);
+    pdf.type1_font(font_id)
+        .base_font(Name(b"Courier"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+
+    for (page_labels, (page_id, content_id)) in pages.iter().zip(&page_ids) {
+        let mut page = pdf.page(*page_id);
+        page.media_box(Rect::new(0.0, 0.0, sheet.page_width, sheet.page_height));
+        page.parent(pages_id);
+        page.contents(*content_id);
+        page.resources().fonts().pair(font_name, font_id);
+        page.finish();
+
+        let mut content = Content::new();
+        for (index, label) in page_labels.iter().enumerate() {
+            draw_label(&mut content, sheet, sheet.origin(index), label, qr, font_name)?;
+        }
+        pdf.stream(*content_id, &content.finish());
+    }
+    Ok(pdf.finish())
+}
+
+fn draw_label(
+    content: &mut Content,
+    sheet: &Sheet,
+    (x, y): (f32, f32),
+    label: &Label,
+    qr: &QrConfig,
+    font_name: Name,
+) -> Result<(), ApiError> {
+    let vehicle = &label.vehicle;
+    let padding = PADDING_MM * POINTS_PER_MM;
+    let lines = [
+        format!("{} {}", vehicle.manufacturer, vehicle.model),
+        format!("ID {}", label.id()),
+    ];
+    let text_height = lines.len() as f32 * LINE_HEIGHT;
+
+    // The code takes the largest square left above the text.
+    let slug = label.slug.as_deref().unwrap_or_default();
+    let code = qr_builder::encode(&qr.payload_for_slug(slug), QrEcLevel::M)?;
+    let modules = code.width() as u32 + 2 * QUIET_ZONE;
+    let side = (sheet.label_width - 2.0 * padding).min(sheet.label_height - 2.0 * padding - text_height);
+    let module = side / modules as f32;
+    let left = x + (sheet.label_width - side) / 2.0;
+    let top = y + sheet.label_height - padding;
+    content.set_fill_gray(0.0);
+    for (mx, my, run) in qr_builder::dark_runs(&code) {
+        content.rect(
+            left + (mx + QUIET_ZONE) as f32 * module,
+            top - (my + QUIET_ZONE + 1) as f32 * module,
+            run as f32 * module,
+            module,
+        );
+    }
+    content.fill_nonzero();
+
+    let max_chars = ((sheet.label_width - 2.0 * padding) / CHAR_WIDTH) as usize;
+    let mut baseline = top - side - FONT_SIZE;
+    for line in &lines {
+        let text = fit(line, max_chars);
+        let width = text.len() as f32 * CHAR_WIDTH;
+        content
+            .begin_text()
+            .set_font(font_name, FONT_SIZE)
+            .next_line(x + (sheet.label_width - width) / 2.0, baseline)
+            .show(Str(&text))
+            .end_text();
+        baseline -= LINE_HEIGHT;
+    }
+    Ok(())
+}
+
+/// Encodes `text` for the WinAnsi font, which covers Latin-1; other
+/// characters print as `?`. Cuts it to `max_chars`, ending in `...`.
+fn fit(text: &str, max_chars: usize) -> Vec<u8> {
+    let mut bytes = text
+        .chars()
+        .map(|c| match c as u32 {
+            0x20..=0x7e | 0xa0..=0xff => c as u8,
+            _ => b'?',
+        })
+        .collect::<Vec<u8>>();
+    if bytes.len() > max_chars {
+        bytes.truncate(max_chars.saturating_sub(3));
+        bytes.extend(b"...");
+    }
+    bytes
+}
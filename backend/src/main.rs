@@ -1,14 +1,39 @@
 #[macro_use]
 extern crate rocket;
 
-use qr_builder::create_qr_for_id;
+use auth::{Admin, Editor, User};
+use error::ApiError;
+use etag::{ETag, IfMatch, Tagged};
+use filter::{Conditions, Paging, VehicleFilter};
+use qr_builder::{QrCache, QrConfig, QrParams};
 use rocket::serde::{json::Json, Deserialize, Serialize};
-use rocket::{http::Status, response::status::Custom, State};
-use rocket_cors::{AllowedOrigins, CorsOptions};
-use tokio::fs::File;
-use tokio_postgres::{Client, NoTls};
+use rocket::http::{ContentType, Status};
+use rocket::State;
+use deadpool_postgres::Pool;
+use price::Price;
+use rust_decimal::Decimal;
+use serde_json::{Map, Value};
+use std::process::ExitCode;
+use tokio_postgres::{Client, Row};
 
+mod auth;
+mod cors;
+mod db;
+mod error;
+mod etag;
+mod export;
+mod filter;
+mod history;
+mod import;
+mod labels;
+mod migrations;
+mod price;
+mod public_slug;
 mod qr_builder;
+mod search;
+mod trash;
+mod users;
+mod visibility;
 
 #[derive(Serialize, Deserialize, Clone)]
 struct Vehicle {
@@ -16,262 +41,467 @@ struct Vehicle {
     vehicle_type: String,
     manufacturer: String,
     model: String,
-    price: String,
-    data: String,
+    price: Option<Price>,
+    /// Original text of a pre-migration price that couldn't be parsed. Such
+    /// rows have no `price` until someone sets one.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    price_legacy: Option<String>,
+    /// Free-form specifications (engine size, mileage, color, ...) as a JSON
+    /// object.
+    #[serde(default = "empty_specs")]
+    data: Value,
+    /// Row version, bumped on every write. Also sent as the `ETag`.
+    #[serde(skip_deserializing)]
+    version: i32,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct QrCodeImage {
-    char_image: String,
+fn empty_specs() -> Value {
+    Value::Object(Map::new())
+}
+
+const VEHICLE_COLUMNS: &str =
+    "id, vehicle_type, manufacturer, model, price_amount, price_currency, price_legacy, data, version";
+
+impl Vehicle {
+    fn from_row(row: &Row) -> Self {
+        let amount: Option<Decimal> = row.get(4);
+        let currency: Option<String> = row.get(5);
+        Vehicle {
+            id: Some(row.get(0)),
+            vehicle_type: row.get(1),
+            manufacturer: row.get(2),
+            model: row.get(3),
+            price: amount.zip(currency).map(|(amount, currency)| Price { amount, currency }),
+            price_legacy: row.get(6),
+            data: row.get(7),
+            version: row.get(8),
+        }
+    }
+
+    fn validate(&self) -> Result<&Price, ApiError> {
+        let price = self
+            .price
+            .as_ref()
+            .ok_or_else(|| ApiError::Validation("Price is required".to_string()))?;
+        validate_required("vehicle_type", &self.vehicle_type)?;
+        validate_required("manufacturer", &self.manufacturer)?;
+        validate_required("model", &self.model)?;
+        price.validate()?;
+        validate_specs(&self.data)?;
+        Ok(price)
+    }
 }
 
+/// Vehicle type, manufacturer and model can't be blank, whether they come
+/// from the form, a PATCH or an import.
+fn validate_required(name: &str, value: &str) -> Result<(), ApiError> {
+    match value.trim().is_empty() {
+        true => Err(ApiError::Validation(format!("{} is missing", name))),
+        false => Ok(()),
+    }
+}
+
+fn validate_specs(data: &Value) -> Result<(), ApiError> {
+    match data.is_object() {
+        true => Ok(()),
+        false => Err(ApiError::Validation(
+            "Data must be a JSON object of specifications".to_string(),
+        )),
+    }
+}
+
+/// Body of `PATCH /api/vehicles/<id>`: only the fields present are changed.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VehiclePatch {
+    vehicle_type: Option<String>,
+    manufacturer: Option<String>,
+    model: Option<String>,
+    price: Option<Price>,
+    data: Option<Value>,
+}
+
+/// Creates a vehicle and returns it with its `ETag`.
 #[post("/api/vehicles", data = "<vehicle>")]
 async fn add_vehicle(
-    conn: &State<Client>,
+    pool: &State<Pool>,
+    user: Editor,
     vehicle: Json<Vehicle>,
-) -> Result<Json<Vec<Vehicle>>, Custom<String>> {
-    execute_query(
-        conn,
-        "INSERT INTO vehicles (vehicle_type, manufacturer, model, price, data) VALUES ($1, $2, $3, $4, $5)",
-        &[&vehicle.vehicle_type, &vehicle.manufacturer, &vehicle.model, &vehicle.price, &vehicle.data],
-    )
-    .await?;
-    get_vehicles(conn).await
+) -> Result<Tagged<Json<Vehicle>>, ApiError> {
+    let price = vehicle.validate()?;
+    let mut client = db::client(pool).await?;
+    let transaction = db::audited(&mut client, &user).await?;
+    let row = transaction
+        .query_one(
+            &format!(
+                "INSERT INTO vehicles (vehicle_type, manufacturer, model, price_amount, price_currency, data) VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+                VEHICLE_COLUMNS
+            ),
+            &[&vehicle.vehicle_type, &vehicle.manufacturer, &vehicle.model, &price.amount, &price.currency, &vehicle.data],
+        )
+        .await?;
+    transaction.commit().await?;
+    let vehicle = Vehicle::from_row(&row);
+    Ok(Tagged {
+        etag: ETag(vehicle.version),
+        inner: Json(vehicle),
+    })
 }
 
-#[get("/api/vehicles")]
-async fn get_vehicles(conn: &State<Client>) -> Result<Json<Vec<Vehicle>>, Custom<String>> {
-    get_vehicles_from_db(conn).await.map(Json)
+#[derive(Serialize)]
+struct VehiclePage<T = Vehicle> {
+    items: Vec<T>,
+    total: i64,
+    limit: i64,
+    offset: i64,
+    next_offset: Option<i64>,
 }
 
-async fn get_vehicles_from_db(client: &Client) -> Result<Vec<Vehicle>, Custom<String>> {
+#[get("/api/vehicles?<limit>&<offset>&<sort>&<filter..>")]
+async fn get_vehicles(
+    pool: &State<Pool>,
+    _user: User,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    sort: Option<&str>,
+    filter: VehicleFilter,
+) -> Result<Json<VehiclePage>, ApiError> {
+    let paging = Paging::new(limit, offset, sort)?;
+    let client = db::client(pool).await?;
+    get_vehicles_from_db(&client, &filter, &paging).await.map(Json)
+}
+
+async fn get_vehicles_from_db(
+    client: &Client,
+    filter: &VehicleFilter,
+    paging: &Paging,
+) -> Result<VehiclePage, ApiError> {
+    let mut conditions = Conditions::default();
+    conditions.push("deleted_at IS NULL".to_string());
+    filter.apply(&mut conditions)?;
+    let total: i64 = client
+        .query_one(
+            &format!(
+                "SELECT COUNT(*) FROM vehicles {}",
+                conditions.where_clause()
+            ),
+            &conditions.params(),
+        )
+        .await?
+        .get(0);
     let vehicles = client
         .query(
-            "SELECT id, vehicle_type, manufacturer, model, price, data FROM vehicles",
-            &[],
+            &format!(
+                "SELECT {} FROM vehicles {} {}",
+                VEHICLE_COLUMNS,
+                conditions.where_clause(),
+                paging.sql()
+            ),
+            &conditions.params(),
         )
-        .await
-        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))?
+        .await?
         .iter()
-        .map(|row| Vehicle {
-            id: Some(row.get(0)),
-            vehicle_type: row.get(1),
-            manufacturer: row.get(2),
-            model: row.get(3),
-            price: row.get(4),
-            data: row.get(5),
-        })
+        .map(Vehicle::from_row)
         .collect::<Vec<Vehicle>>();
-    Ok(vehicles)
+    Ok(VehiclePage {
+        items: vehicles,
+        total,
+        limit: paging.limit,
+        offset: paging.offset,
+        next_offset: paging.next_offset(total),
+    })
 }
 
+/// Staff only: ids are sequential, so an open route would let anyone list
+/// the fleet. QR codes resolve through `public_slug::resolve_slug` instead.
 #[get("/api/vehicles/<id>")]
-async fn get_vehicle_info(conn: &State<Client>, id: i32) -> Result<Json<Vehicle>, Custom<String>> {
-    get_vehicle_info_from_db(conn, id).await.map(Json)
+async fn get_vehicle_info(
+    pool: &State<Pool>,
+    _user: User,
+    id: i32,
+) -> Result<Tagged<Json<Vehicle>>, ApiError> {
+    let client = db::client(pool).await?;
+    let vehicle = get_vehicle_info_from_db(&client, id).await?;
+    Ok(Tagged {
+        etag: ETag(vehicle.version),
+        inner: Json(vehicle),
+    })
 }
-async fn get_vehicle_info_from_db(client: &Client, id: i32) -> Result<Vehicle, Custom<String>> {
+async fn get_vehicle_info_from_db(client: &Client, id: i32) -> Result<Vehicle, ApiError> {
     let vehicles = client
         .query(
-            "SELECT * FROM vehicles WHERE id = $1",
+            &format!("SELECT {} FROM vehicles WHERE id = $1 AND deleted_at IS NULL", VEHICLE_COLUMNS),
             &[&id],
         )
-        .await
-        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))?
+        .await?
         .iter()
-        .map(|row| Vehicle {
-            id: Some(row.get(0)),
-            vehicle_type: row.get(1),
-            manufacturer: row.get(2),
-            model: row.get(3),
-            price: row.get(4),
-            data: row.get(5),
-        })
+        .map(Vehicle::from_row)
         .collect::<Vec<Vehicle>>();
     match vehicles.len() {
-        0 => Err(Custom(Status::InternalServerError, "Vehicle with ID not found in the database".to_string())),
+        0 => Err(ApiError::NotFound("Vehicle with ID not found in the database".to_string())),
         1 => Ok(vehicles[0].clone()),
-        _ => {Err(Custom(Status::InternalServerError, "Something is definitely wrong with the database".to_string()))}
+        _ => {Err(ApiError::Internal("Something is definitely wrong with the database".to_string()))}
     }
 
 }
 
-#[get("/api/vehicles/search/<model>")]
-async fn fuzzy_search_vehicles(
-    conn: &State<Client>,
-    model: &str,
-) -> Result<Json<Vec<Vehicle>>, Custom<String>> {
-    fuzzy_search_from_db(conn, model).await.map(Json)
-}
-
-async fn fuzzy_search_from_db(
-    client: &Client,
-    model: &str,
-) -> Result<Vec<Vehicle>, Custom<String>> {
-    let vehicles = client
-        .query("SELECT id, vehicle_type, manufacturer, model, price, data FROM vehicles ORDER BY LEVENSHTEIN(model, $1) ASC LIMIT 10", &[&model])
-        .await
-        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))?
-        .iter()
-        .map(|row| Vehicle {
-            id: Some(row.get(0)),
-            vehicle_type: row.get(1),
-            manufacturer: row.get(2),
-            model: row.get(3),
-            price: row.get(4),
-            data: row.get(5),
-        })
-        .collect::<Vec<Vehicle>>();
-    Ok(vehicles)
+/// Explains why a write guarded by `If-Match` touched no row: either the
+/// vehicle is gone, or it has a newer version than the client had.
+async fn write_conflict(client: &Client, id: i32) -> ApiError {
+    match get_vehicle_info_from_db(client, id).await {
+        Ok(current) => ApiError::PreconditionFailed(
+            "Vehicle was changed since it was read".to_string(),
+            serde_json::to_value(current).unwrap_or_default(),
+        ),
+        Err(e) => e,
+    }
 }
 
-#[get("/api/vehicles/search/unique/<column>")]
-async fn search_unique_cols_vehicles(
-    conn: &State<Client>,
-    column: &str,
-) -> Result<Json<Vec<String>>, Custom<String>> {
-    search_unique_from_db(conn, column).await.map(Json)
+/// Replaces every field of a vehicle and returns it with its new `ETag`.
+#[put("/api/vehicles/<id>", data = "<vehicle>")]
+async fn update_vehicle(
+    pool: &State<Pool>,
+    id: i32,
+    if_match: IfMatch,
+    user: Editor,
+    vehicle: Json<Vehicle>,
+) -> Result<Tagged<Json<Vehicle>>, ApiError> {
+    let price = vehicle.validate()?;
+    let mut client = db::client(pool).await?;
+    let transaction = db::audited(&mut client, &user).await?;
+    let row = transaction
+        .query_opt(
+            &format!(
+                "UPDATE vehicles SET vehicle_type = $1, manufacturer = $2, model = $3, price_amount = $4, price_currency = $5, price_legacy = NULL, data = $6 WHERE id = $7 AND deleted_at IS NULL AND ($8::INT[] IS NULL OR version = ANY($8)) RETURNING {}",
+                VEHICLE_COLUMNS
+            ),
+            &[&vehicle.vehicle_type, &vehicle.manufacturer, &vehicle.model, &price.amount, &price.currency, &vehicle.data, &id, &if_match.0],
+        )
+        .await?;
+    transaction.commit().await?;
+    let Some(row) = row else {
+        return Err(write_conflict(&client, id).await);
+    };
+    let vehicle = Vehicle::from_row(&row);
+    Ok(Tagged {
+        etag: ETag(vehicle.version),
+        inner: Json(vehicle),
+    })
 }
 
-async fn search_unique_from_db(
-    client: &Client,
-    col_name: &str,
-) -> Result<Vec<String>, Custom<String>> {
-    let query = format!(
-        "SELECT DISTINCT {} FROM vehicles",
-        match col_name {
-            c if c == "vehicle_type"
-                || c == "manufacturer"
-                || c == "model"
-                || c == "price"
-                || c == "data" =>
-            {
-                c
-            }
-            _ => {
-                return Err(Custom(
-                    Status::InternalServerError,
-                    "Unmatched column name given".to_string(),
-                ));
-            }
+#[patch("/api/vehicles/<id>", data = "<patch>")]
+async fn patch_vehicle(
+    pool: &State<Pool>,
+    id: i32,
+    if_match: IfMatch,
+    user: Editor,
+    patch: Json<VehiclePatch>,
+) -> Result<Tagged<Json<Vehicle>>, ApiError> {
+    let patch = patch.into_inner();
+    let mut query = Conditions::default();
+    let mut assignments = Vec::new();
+    for (column, value) in [
+        ("vehicle_type", patch.vehicle_type),
+        ("manufacturer", patch.manufacturer),
+        ("model", patch.model),
+    ] {
+        if let Some(value) = value {
+            validate_required(column, &value)?;
+            assignments.push(format!("{} = {}", column, query.param(value)));
         }
-    );
-    let unique_rows = client
-        .query(&query, &[])
-        .await
-        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))?
-        .iter()
-        .map(|row| {
-            row.get(0)
-        })
-        .collect::<Vec<String>>();
+    }
+    if let Some(price) = patch.price {
+        price.validate()?;
+        assignments.push(format!("price_amount = {}", query.param(price.amount)));
+        assignments.push(format!("price_currency = {}", query.param(price.currency)));
+        assignments.push("price_legacy = NULL".to_string());
+    }
+    if let Some(data) = patch.data {
+        validate_specs(&data)?;
+        assignments.push(format!("data = {}", query.param(data)));
+    }
+    if assignments.is_empty() {
+        return Err(ApiError::Validation("No fields to update".to_string()));
+    }
 
-    Ok(unique_rows)
+    let mut client = db::client(pool).await?;
+    let id_param = query.param(id);
+    let versions = query.param(if_match.0);
+    let transaction = db::audited(&mut client, &user).await?;
+    let row = transaction
+        .query_opt(
+            &format!(
+                "UPDATE vehicles SET {} WHERE id = {} AND deleted_at IS NULL AND ({versions}::INT[] IS NULL OR version = ANY({versions})) RETURNING {}",
+                assignments.join(", "),
+                id_param,
+                VEHICLE_COLUMNS
+            ),
+            &query.params(),
+        )
+        .await?;
+    transaction.commit().await?;
+    let Some(row) = row else {
+        return Err(write_conflict(&client, id).await);
+    };
+    let vehicle = Vehicle::from_row(&row);
+    Ok(Tagged {
+        etag: ETag(vehicle.version),
+        inner: Json(vehicle),
+    })
 }
 
-#[put("/api/vehicles/<id>", data = "<vehicle>")]
-async fn update_vehicle(
-    conn: &State<Client>,
+/// Moves a vehicle to the trash; see `trash` for restoring and purging.
+#[delete("/api/vehicles/<id>")]
+async fn delete_vehicle(
+    pool: &State<Pool>,
     id: i32,
-    vehicle: Json<Vehicle>,
-) -> Result<Json<Vec<Vehicle>>, Custom<String>> {
-    execute_query(
-        conn,
-        "UPDATE vehicles SET vehicle_type = $1, manufacturer = $2, model = $3, price = $4, data = $5 WHERE id = $6",
-        &[&vehicle.vehicle_type, &vehicle.manufacturer, &vehicle.model, &vehicle.price, &vehicle.data, &id],
-    )
-    .await?;
-    get_vehicles(conn).await
+    if_match: IfMatch,
+    user: Admin,
+) -> Result<Status, ApiError> {
+    let mut client = db::client(pool).await?;
+    let transaction = db::audited(&mut client, &user).await?;
+    let deleted = transaction
+        .execute(
+            "UPDATE vehicles SET deleted_at = now(), deleted_by = $3 WHERE id = $1 AND deleted_at IS NULL AND ($2::INT[] IS NULL OR version = ANY($2))",
+            &[&id, &if_match.0, &user.username],
+        )
+        .await?;
+    transaction.commit().await?;
+    if deleted == 0 {
+        return Err(write_conflict(&client, id).await);
+    }
+    Ok(Status::NoContent)
 }
 
-#[delete("/api/vehicles/<id>")]
-async fn delete_vehicle(conn: &State<Client>, id: i32) -> Result<Status, Custom<String>> {
-    execute_query(conn, "DELETE from vehicles WHERE id = $1", &[&id]).await?;
-    Ok(Status::NoContent)
+async fn ensure_vehicle_exists(client: &Client, id: i32) -> Result<(), ApiError> {
+    let exists: bool = client
+        .query_one(
+            "SELECT EXISTS(SELECT * FROM vehicles WHERE id = $1 AND deleted_at IS NULL)",
+            &[&id],
+        )
+        .await?
+        .get(0);
+    match exists {
+        true => Ok(()),
+        false => Err(ApiError::NotFound("Entry with such id doesn't exist".to_string())),
+    }
 }
 
-#[get("/api/vehicles/qr/<id>")]
+/// The QR code of a vehicle. See `QrParams` for the format, size, color and
+/// error correction options.
+#[get("/api/vehicles/qr/<id>?<params..>")]
 async fn generate_qr_vehicle(
-    conn: &State<Client>,
+    pool: &State<Pool>,
+    _user: User,
+    qr: &State<QrConfig>,
+    qr_cache: &State<QrCache>,
     id: i32,
-) -> Result<Option<File>, Custom<String>> {
-    let res = conn.query(
-        "SELECT EXISTS(SELECT * FROM vehicles WHERE id = $1)",
-        &[&id],
-    )
-    .await
-    .map_err(|e| Custom(Status::InternalServerError, e.to_string()))?
-    .iter()
-    .map(|row| {let res:bool = row.get(0); println!("exists?: {}", res.clone()); res})
-    .collect::<Vec<bool>>();
-    match res[0] {
-        true => {},
-        false => {return Err(Custom(Status::InternalServerError, "Entry with such id doesn't exist".to_string()))}
-    }
-
-    let path = create_qr_for_id(id);
-    match File::open(path).await {
-        Ok(f) => Ok(Some(f)),
-        Err(e) => Err(Custom(Status::InternalServerError, e.to_string())),
-    }
+    params: QrParams,
+) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let options = params.resolve()?;
+    let client = db::client(pool).await?;
+    let slug = public_slug::slug_for(&client, id).await?;
+    qr_cache.get(&qr.payload_for_slug(&slug), &options).await
 }
 
-async fn execute_query(
-    client: &Client,
-    query: &str,
-    params: &[&(dyn tokio_postgres::types::ToSql + Sync)],
-) -> Result<u64, Custom<String>> {
-    client
-        .execute(query, params)
-        .await
-        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))
+#[derive(Serialize)]
+struct QrTarget {
+    id: i32,
+    slug: String,
+    url: String,
 }
 
-#[launch]
-async fn rocket() -> _ {
-    let (client, connection) = tokio_postgres::connect(
-        "host=localhost user=postgres password=postgres dbname=postgres",
-        NoTls,
-    )
-    .await
-    .expect("Failed to connect to Postgres");
+/// The URL a vehicle's QR code encodes.
+#[get("/api/vehicles/qr/<id>/url")]
+async fn get_qr_url(
+    pool: &State<Pool>,
+    _user: User,
+    qr: &State<QrConfig>,
+    id: i32,
+) -> Result<Json<QrTarget>, ApiError> {
+    let client = db::client(pool).await?;
+    let slug = public_slug::slug_for(&client, id).await?;
+    Ok(Json(QrTarget {
+        id,
+        url: qr.payload_for_slug(&slug),
+        slug,
+    }))
+}
 
-    tokio::spawn(async move {
-        if let Err(e) = connection.await {
-            eprintln!("Failed to connect to Postgres {}", e);
-        }
-    });
-    client
-        .execute(
-            "CREATE TABLE IF NOT EXISTS vehicles (
-                id SERIAL PRIMARY KEY,
-                vehicle_type TEXT NOT NULL,
-                manufacturer TEXT NOT NULL,
-                model TEXT NOT NULL,
-                price TEXT NOT NULL,
-                data TEXT NOT NULL
-        )",
-            &[],
-        )
-        .await
-        .expect("Failed to create table");
-    let cors = CorsOptions::default()
-        .allowed_origins(AllowedOrigins::all())
-        .to_cors()
-        .expect("Error while building CORS");
+fn rocket() -> rocket::Rocket<rocket::Build> {
     rocket::build()
-        .manage(client)
+        .attach(db::stage())
+        .attach(trash::stage())
+        .attach(qr_builder::stage())
+        .attach(auth::stage())
+        .attach(cors::stage())
         .mount(
             "/",
             routes![
                 add_vehicle,
                 get_vehicles,
                 update_vehicle,
+                patch_vehicle,
                 delete_vehicle,
-                fuzzy_search_vehicles,
-                search_unique_cols_vehicles,
+                search::fuzzy_search_vehicles,
+                search::text_search_vehicles,
+                search::search_unique_cols_vehicles,
+                search::facet_vehicles,
+                trash::get_trash,
+                trash::restore_vehicle,
+                history::get_vehicle_history,
+                import::import_vehicles,
+                export::export_vehicles,
+                labels::vehicle_labels,
+                public_slug::resolve_slug,
+                public_slug::regenerate_slug,
+                public_slug::revoke_slug,
+                auth::login,
+                auth::logout,
+                auth::me,
+                auth::get_api_tokens,
+                auth::create_api_token,
+                auth::revoke_api_token,
+                users::get_users,
+                users::create_user,
+                users::set_user_role,
+                users::delete_user,
+                visibility::get_visibility,
+                visibility::set_visibility,
                 generate_qr_vehicle,
+                get_qr_url,
                 get_vehicle_info,
             ],
         )
-        .attach(cors)
+        .register("/", catchers![error::default_catcher])
+}
+
+async fn migrate(command: Option<&str>) -> Result<(), String> {
+    let config = db::DatabaseConfig::from_figment(&rocket::Config::figment())?;
+    let pool = config.build_pool()?;
+    let mut client = pool.get().await.map_err(|e| db::describe_pool_error(&e))?;
+    migrations::cli(&mut client, command)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[rocket::main]
+async fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let result = match args.first().map(String::as_str) {
+        Some("migrate") => migrate(args.get(1).map(String::as_str)).await,
+        _ => rocket()
+            .launch()
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+    };
+    // Printed with `Display`, so multi-line database errors stay readable.
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
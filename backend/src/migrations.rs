@@ -0,0 +1,392 @@
+use std::fmt;
+
+use tokio_postgres::{Client, Error};
+
+use crate::db;
+
+/// A schema change kept in `backend/migrations`. Versions are applied in
+/// ascending order and recorded in `schema_migrations`.
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_vehicles",
+        sql: include_str!("../migrations/0001_create_vehicles.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "numeric_price",
+        sql: include_str!("../migrations/0002_numeric_price.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "jsonb_specs",
+        sql: include_str!("../migrations/0003_jsonb_specs.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "fuzzy_search",
+        sql: include_str!("../migrations/0004_fuzzy_search.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "full_text_search",
+        sql: include_str!("../migrations/0005_full_text_search.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "row_versions",
+        sql: include_str!("../migrations/0006_row_versions.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "soft_delete",
+        sql: include_str!("../migrations/0007_soft_delete.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "audit_history",
+        sql: include_str!("../migrations/0008_audit_history.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "public_slugs",
+        sql: include_str!("../migrations/0009_public_slugs.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "users",
+        sql: include_str!("../migrations/0010_users.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "roles",
+        sql: include_str!("../migrations/0011_roles.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "field_visibility",
+        sql: include_str!("../migrations/0012_field_visibility.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "fuzzy_search_index",
+        sql: include_str!("../migrations/0013_fuzzy_search_index.sql"),
+    },
+];
+
+/// Arbitrary key for `pg_advisory_lock`, so two instances starting at the
+/// same time don't apply the same migration twice.
+const LOCK_KEY: i64 = 0x7665_6869_636c_6573;
+
+async fn ensure_table(client: &Client) -> Result<(), Error> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                checksum BYTEA NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+        )
+        .await
+}
+
+/// Why applying or inspecting migrations failed.
+#[derive(Debug)]
+pub enum MigrationError {
+    /// A migration's SQL failed; its transaction was rolled back.
+    Failed(&'static Migration, Error),
+    /// The SQL of an applied migration no longer matches what was run.
+    /// Shipped migrations must not be edited; add a new one instead.
+    Changed(&'static Migration),
+    /// Reading or recording which migrations have run failed.
+    Database(Error),
+    UnknownCommand(String),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Failed(migration, e) => write!(
+                f,
+                "Migration {:04} {} failed: {}",
+                migration.version,
+                migration.name,
+                db::describe(e, Some(migration.sql))
+            ),
+            MigrationError::Changed(migration) => write!(
+                f,
+                "Migration {:04} {} was edited after it was applied; restore its SQL and put the change in a new migration",
+                migration.version, migration.name
+            ),
+            MigrationError::Database(e) => write!(f, "{}", db::describe(e, None)),
+            MigrationError::UnknownCommand(command) => write!(
+                f,
+                "Unknown migrate command `{}`, expected status, up or dry-run",
+                command
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<Error> for MigrationError {
+    fn from(e: Error) -> Self {
+        MigrationError::Database(e)
+    }
+}
+
+impl fmt::Debug for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04} {}", self.version, self.name)
+    }
+}
+
+pub struct Status {
+    pub migration: &'static Migration,
+    pub applied_at: Option<String>,
+}
+
+/// Lists every migration with when it was applied, without writing
+/// anything; before the first `run` there is no `schema_migrations` table and
+/// nothing counts as applied. Fails when the SQL of an applied migration
+/// changed since.
+pub async fn status(client: &Client) -> Result<Vec<Status>, MigrationError> {
+    let exists: bool = client
+        .query_one("SELECT to_regclass('schema_migrations') IS NOT NULL", &[])
+        .await?
+        .get(0);
+    let applied = match exists {
+        false => Vec::new(),
+        true => {
+            let versions = MIGRATIONS.iter().map(|m| m.version).collect::<Vec<i32>>();
+            let sql = MIGRATIONS.iter().map(|m| m.sql).collect::<Vec<&str>>();
+            client
+                .query(
+                    "SELECT s.version, s.applied_at::TEXT, s.checksum = sha256(convert_to(m.sql, 'UTF8'))
+                    FROM schema_migrations s
+                    JOIN unnest($1::INT[], $2::TEXT[]) AS m(version, sql) USING (version)",
+                    &[&versions, &sql],
+                )
+                .await?
+                .iter()
+                .map(|row| (row.get::<_, i32>(0), row.get::<_, String>(1), row.get::<_, bool>(2)))
+                .collect::<Vec<(i32, String, bool)>>()
+        }
+    };
+
+    MIGRATIONS
+        .iter()
+        .map(|migration| {
+            let applied = applied
+                .iter()
+                .find(|(version, _, _)| *version == migration.version);
+            match applied {
+                Some((_, _, false)) => Err(MigrationError::Changed(migration)),
+                _ => Ok(Status {
+                    migration,
+                    applied_at: applied.map(|(_, at, _)| at.clone()),
+                }),
+            }
+        })
+        .collect()
+}
+
+pub async fn pending(client: &Client) -> Result<Vec<&'static Migration>, MigrationError> {
+    Ok(status(client)
+        .await?
+        .into_iter()
+        .filter(|s| s.applied_at.is_none())
+        .map(|s| s.migration)
+        .collect())
+}
+
+/// Applies every pending migration, each in its own transaction, and returns
+/// the ones that ran.
+pub async fn run(client: &mut Client) -> Result<Vec<&'static Migration>, MigrationError> {
+    client
+        .execute("SELECT pg_advisory_lock($1)", &[&LOCK_KEY])
+        .await?;
+    let result = apply_pending(client).await;
+    client
+        .execute("SELECT pg_advisory_unlock($1)", &[&LOCK_KEY])
+        .await?;
+    result
+}
+
+async fn apply_pending(client: &mut Client) -> Result<Vec<&'static Migration>, MigrationError> {
+    ensure_table(client).await?;
+    let pending = pending(client).await?;
+    for migration in &pending {
+        let transaction = client.transaction().await?;
+        transaction
+            .batch_execute(migration.sql)
+            .await
+            .map_err(|e| MigrationError::Failed(migration, e))?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version, name, checksum)
+                VALUES ($1, $2, sha256(convert_to($3, 'UTF8')))",
+                &[&migration.version, &migration.name, &migration.sql],
+            )
+            .await?;
+        transaction.commit().await?;
+    }
+    Ok(pending)
+}
+
+/// Entry point for `backend migrate [status|up|dry-run]`.
+pub async fn cli(client: &mut Client, command: Option<&str>) -> Result<(), MigrationError> {
+    match command.unwrap_or("status") {
+        "status" => {
+            for s in status(client).await? {
+                println!(
+                    "{:04} {:<32} {}",
+                    s.migration.version,
+                    s.migration.name,
+                    s.applied_at.as_deref().unwrap_or("pending")
+                );
+            }
+        }
+        "dry-run" => {
+            let pending = pending(client).await?;
+            if pending.is_empty() {
+                println!("Schema is up to date");
+            }
+            for migration in pending {
+                println!("-- {:04} {}\n{}", migration.version, migration.name, migration.sql);
+            }
+        }
+        "up" => {
+            let applied = run(client).await?;
+            if applied.is_empty() {
+                println!("Schema is up to date");
+            }
+            for migration in applied {
+                println!("Applied {:04} {}", migration.version, migration.name);
+            }
+        }
+        other => return Err(MigrationError::UnknownCommand(other.to_string())),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_postgres::NoTls;
+
+    use super::{ensure_table, status, MigrationError, MIGRATIONS};
+
+    /// Runs against the database in `DATABASE_URL` when it's set, inside a
+    /// transaction that is rolled back.
+    #[tokio::test]
+    async fn numeric_price_keeps_unreadable_prices_as_legacy_text() {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            eprintln!("DATABASE_URL is not set; skipping");
+            return;
+        };
+        let (mut client, connection) = tokio_postgres::connect(&url, NoTls).await.unwrap();
+        tokio::spawn(connection);
+        let transaction = client.transaction().await.unwrap();
+        transaction
+            .batch_execute(
+                "CREATE SCHEMA migration_test;
+                SET LOCAL search_path TO migration_test;",
+            )
+            .await
+            .unwrap();
+        transaction.batch_execute(MIGRATIONS[0].sql).await.unwrap();
+        transaction
+            .batch_execute(
+                "INSERT INTO vehicles (vehicle_type, manufacturer, model, price, data) VALUES
+                    ('car', 'Toyota', 'Corolla', '$12,500.50', '{}'),
+                    ('car', 'Fiat', 'Panda', '15K EUR', '{}'),
+                    ('car', 'Ford', 'Focus', 'call us', '{}'),
+                    ('car', 'Tesla', 'Roadster', '999999999999M', '{}');",
+            )
+            .await
+            .unwrap();
+        transaction.batch_execute(MIGRATIONS[1].sql).await.unwrap();
+        let prices = transaction
+            .query(
+                "SELECT price_amount::TEXT, price_currency::TEXT, price_legacy FROM vehicles ORDER BY id",
+                &[],
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect::<Vec<(Option<String>, Option<String>, Option<String>)>>();
+        let some = |s: &str| Some(s.to_string());
+        assert_eq!(
+            prices,
+            [
+                (some("12500.50"), some("USD"), None),
+                (some("15000.00"), some("EUR"), None),
+                (None, None, some("call us")),
+                (None, None, some("999999999999M")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn status_refuses_edited_migrations() {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            eprintln!("DATABASE_URL is not set; skipping");
+            return;
+        };
+        let (client, connection) = tokio_postgres::connect(&url, NoTls).await.unwrap();
+        tokio::spawn(connection);
+        client
+            .batch_execute(
+                "DROP SCHEMA IF EXISTS checksum_test CASCADE;
+                CREATE SCHEMA checksum_test;
+                SET search_path TO checksum_test;",
+            )
+            .await
+            .unwrap();
+        // Without the table nothing is applied, and status doesn't create it.
+        let fresh = status(&client).await.unwrap();
+        assert!(fresh.iter().all(|s| s.applied_at.is_none()));
+        let created: bool = client
+            .query_one("SELECT to_regclass('schema_migrations') IS NOT NULL", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert!(!created);
+
+        ensure_table(&client).await.unwrap();
+        client
+            .execute(
+                "INSERT INTO schema_migrations (version, name, checksum)
+                VALUES (1, 'create_vehicles', sha256(convert_to($1, 'UTF8')))",
+                &[&MIGRATIONS[0].sql],
+            )
+            .await
+            .unwrap();
+        let applied = status(&client).await.unwrap();
+        assert!(applied[0].applied_at.is_some());
+        assert!(applied[1].applied_at.is_none());
+
+        client
+            .execute(
+                "UPDATE schema_migrations SET checksum = sha256(convert_to('edited', 'UTF8'))",
+                &[],
+            )
+            .await
+            .unwrap();
+        let edited = status(&client).await;
+        client
+            .batch_execute("DROP SCHEMA checksum_test CASCADE")
+            .await
+            .unwrap();
+        assert!(matches!(edited, Err(MigrationError::Changed(m)) if m.version == 1));
+    }
+}
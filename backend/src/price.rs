@@ -0,0 +1,53 @@
+use rocket::serde::{Deserialize, Serialize};
+use rust_decimal::Decimal;
+
+use crate::error::ApiError;
+
+/// Active ISO 4217 currency codes.
+const CURRENCIES: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT",
+    "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD",
+    "CDF", "CHF", "CLP", "CNY", "COP", "CRC", "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD",
+    "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS", "GIP", "GMD", "GNF", "GTQ",
+    "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR", "ISK", "JMD", "JOD",
+    "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT", "LAK", "LBP", "LKR",
+    "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR",
+    "MWK", "MXN", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN",
+    "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR",
+    "SDG", "SEK", "SGD", "SHP", "SLE", "SOS", "SRD", "SSP", "STN", "SVC", "SYP", "SZL", "THB",
+    "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "UYU", "UZS",
+    "VES", "VND", "VUV", "WST", "XAF", "XCD", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWG",
+];
+
+/// `NUMERIC(14, 2)` leaves twelve digits before the decimal point.
+const MAX_INTEGER_DIGITS: u32 = 12;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Price {
+    pub amount: Decimal,
+    pub currency: String,
+}
+
+impl Price {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.amount.is_sign_negative() {
+            return Err(ApiError::Validation("Price can't be negative".to_string()));
+        }
+        if self.amount.normalize().scale() > 2 {
+            return Err(ApiError::Validation(
+                "Price can have at most two decimal places".to_string(),
+            ));
+        }
+        if self.amount >= Decimal::from(10i64.pow(MAX_INTEGER_DIGITS)) {
+            return Err(ApiError::Validation("Price is too large".to_string()));
+        }
+        if !CURRENCIES.contains(&self.currency.as_str()) {
+            return Err(ApiError::Validation(format!(
+                "`{}` is not an ISO 4217 currency code",
+                self.currency
+            )));
+        }
+        Ok(())
+    }
+}
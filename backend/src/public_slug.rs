@@ -0,0 +1,102 @@
+use deadpool_postgres::Pool;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use tokio_postgres::Client;
+
+use crate::auth::{Editor, User};
+use crate::error::ApiError;
+use crate::qr_builder::QrConfig;
+use crate::visibility::{self, Audience, PublicVehicle};
+use crate::{db, ensure_vehicle_exists, QrTarget, Vehicle, VEHICLE_COLUMNS};
+
+/// The public slug a vehicle's QR code encodes.
+pub async fn slug_for(client: &Client, id: i32) -> Result<String, ApiError> {
+    let row = client
+        .query_opt(
+            "SELECT public_slug FROM vehicles WHERE id = $1 AND deleted_at IS NULL",
+            &[&id],
+        )
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Entry with such id doesn't exist".to_string()))?;
+    row.get::<_, Option<String>>(0).ok_or_else(|| {
+        ApiError::Conflict("The QR code of this vehicle was revoked; generate a new one".to_string())
+    })
+}
+
+/// Looks up the vehicle behind a QR code, showing only its public fields
+/// unless the request is signed in. Revoked and unknown slugs are
+/// indistinguishable.
+#[get("/api/public/vehicles/<slug>")]
+pub async fn resolve_slug(
+    pool: &State<Pool>,
+    slug: &str,
+    user: Option<User>,
+) -> Result<Json<PublicVehicle>, ApiError> {
+    let client = db::client(pool).await?;
+    let row = client
+        .query_opt(
+            &format!(
+                "SELECT {} FROM vehicles WHERE public_slug = $1 AND deleted_at IS NULL",
+                VEHICLE_COLUMNS
+            ),
+            &[&slug],
+        )
+        .await?
+        .ok_or_else(|| ApiError::NotFound("No vehicle is registered under this code".to_string()))?;
+    let audience = match user {
+        Some(_) => Audience::Staff,
+        None => Audience::Public(visibility::public_fields(&client).await?),
+    };
+    Ok(Json(PublicVehicle::project(Vehicle::from_row(&row), &audience)))
+}
+
+/// Gives a vehicle a new slug, e.g. after a sticker was lost. Stickers
+/// printed before stop resolving.
+#[post("/api/vehicles/<id>/slug")]
+pub async fn regenerate_slug(
+    pool: &State<Pool>,
+    qr: &State<QrConfig>,
+    id: i32,
+    user: Editor,
+) -> Result<Json<QrTarget>, ApiError> {
+    let mut client = db::client(pool).await?;
+    let transaction = db::audited(&mut client, &user).await?;
+    let row = transaction
+        .query_opt(
+            "UPDATE vehicles SET public_slug = new_public_slug()
+            WHERE id = $1 AND deleted_at IS NULL
+            RETURNING public_slug",
+            &[&id],
+        )
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Entry with such id doesn't exist".to_string()))?;
+    transaction.commit().await?;
+    let slug: String = row.get(0);
+    Ok(Json(QrTarget {
+        id,
+        url: qr.payload_for_slug(&slug),
+        slug,
+    }))
+}
+
+/// Revokes a vehicle's slug, so its stickers stop resolving, without
+/// issuing a new one.
+#[delete("/api/vehicles/<id>/slug")]
+pub async fn revoke_slug(pool: &State<Pool>, id: i32, user: Editor) -> Result<Status, ApiError> {
+    let mut client = db::client(pool).await?;
+    let transaction = db::audited(&mut client, &user).await?;
+    let revoked = transaction
+        .execute(
+            "UPDATE vehicles SET public_slug = NULL
+            WHERE id = $1 AND deleted_at IS NULL AND public_slug IS NOT NULL",
+            &[&id],
+        )
+        .await?;
+    transaction.commit().await?;
+    if revoked == 0 {
+        // Either there's no such vehicle or its slug was already revoked.
+        ensure_vehicle_exists(&client, id).await?;
+    }
+    Ok(Status::NoContent)
+}
@@ -1,17 +1,450 @@
-use std::fs;
-
-use qrcode::QrCode;
-use image::Luma;
-const BASE_URL: &str = "local.cymn.com:8080/";
-
-pub fn create_qr_for_id(id: i32) -> String {
-    let url = BASE_URL.to_string() + &id.to_string();
-    let qr = QrCode::new(url.clone()).unwrap();
-    let img = qr.render::<Luma<u8>>().build();
-    let path = format!("./results/qr_codes/id_{}.png",id);
-    fs::create_dir_all("./results/qr_codes").unwrap();
-    img.save(path.clone()).unwrap();
-    // let char_img= qr.render::<char>().build();    
-    // println!("data: {url}\nqr:\n{}", char_img);
-    path
-}   
\ No newline at end of file
+use std::collections::{HashMap, VecDeque};
+use std::io::Cursor;
+use std::sync::Mutex;
+
+use image::{ImageFormat, Rgb, RgbImage};
+use qrcode::{Color, EcLevel, QrCode};
+use rocket::fairing::AdHoc;
+use rocket::http::uri::Absolute;
+use rocket::http::ContentType;
+use rocket::serde::Deserialize;
+
+use crate::error::ApiError;
+
+/// Settings read from the `qr` table of the active Rocket profile.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct QrConfig {
+    /// Public URL vehicle QR codes link to, with scheme and path prefix,
+    /// e.g. `https://fleet.example.com/v/`. The vehicle's public slug is
+    /// appended.
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    /// Number of rendered images kept in memory; 0 disables the cache.
+    #[serde(default = "default_cache_size")]
+    pub cache_size: usize,
+}
+
+fn default_base_url() -> String {
+    "http://local.cymn.com:8080/v/".to_string()
+}
+
+fn default_cache_size() -> usize {
+    256
+}
+
+impl Default for QrConfig {
+    fn default() -> Self {
+        QrConfig {
+            base_url: default_base_url(),
+            cache_size: default_cache_size(),
+        }
+    }
+}
+
+impl QrConfig {
+    /// Checks that `base_url` is an absolute http(s) URL without a query and
+    /// makes sure it ends with `/`.
+    pub fn validate(&mut self) -> Result<(), String> {
+        let uri = Absolute::parse(&self.base_url)
+            .map_err(|e| format!("qr.base_url `{}` is not a URL: {}", self.base_url, e))?;
+        if !["http", "https"].contains(&uri.scheme().to_ascii_lowercase().as_str()) {
+            return Err(format!(
+                "qr.base_url `{}` must start with http:// or https://",
+                self.base_url
+            ));
+        }
+        if uri.authority().is_none_or(|a| a.host().is_empty()) {
+            return Err(format!("qr.base_url `{}` has no host", self.base_url));
+        }
+        if uri.query().is_some() {
+            return Err(format!(
+                "qr.base_url `{}` can't have a query string",
+                self.base_url
+            ));
+        }
+        if !self.base_url.ends_with('/') {
+            self.base_url.push('/');
+        }
+        Ok(())
+    }
+
+    /// What the QR code of a vehicle with this public slug encodes.
+    pub fn payload_for_slug(&self, slug: &str) -> String {
+        format!("{}{}", self.base_url, slug)
+    }
+}
+
+const MAX_MODULE_SIZE: u32 = 64;
+const MAX_QUIET_ZONE: u32 = 16;
+/// Largest PNG side in pixels: plenty for a label printer, and small enough
+/// that a request can't make us allocate huge images.
+const MAX_PNG_SIDE: u32 = 2048;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+    /// Unicode block characters, for printing in a terminal.
+    Text,
+}
+
+impl QrFormat {
+    fn parse(value: &str) -> Result<Self, ApiError> {
+        match value {
+            "png" => Ok(QrFormat::Png),
+            "svg" => Ok(QrFormat::Svg),
+            "text" => Ok(QrFormat::Text),
+            _ => Err(ApiError::BadRequest(format!(
+                "format must be png, svg or text, got `{}`",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum QrEcLevel {
+    L,
+    #[default]
+    M,
+    Q,
+    H,
+}
+
+impl QrEcLevel {
+    fn parse(value: &str) -> Result<Self, ApiError> {
+        match value.to_ascii_uppercase().as_str() {
+            "L" => Ok(QrEcLevel::L),
+            "M" => Ok(QrEcLevel::M),
+            "Q" => Ok(QrEcLevel::Q),
+            "H" => Ok(QrEcLevel::H),
+            _ => Err(ApiError::BadRequest(format!(
+                "ec must be L, M, Q or H, got `{}`",
+                value
+            ))),
+        }
+    }
+}
+
+impl From<QrEcLevel> for EcLevel {
+    fn from(level: QrEcLevel) -> Self {
+        match level {
+            QrEcLevel::L => EcLevel::L,
+            QrEcLevel::M => EcLevel::M,
+            QrEcLevel::Q => EcLevel::Q,
+            QrEcLevel::H => EcLevel::H,
+        }
+    }
+}
+
+/// Query string of `/api/vehicles/qr/<id>`. Values are kept as text and
+/// checked by `resolve`, so a typo is rejected rather than silently replaced
+/// by the default.
+#[derive(FromForm, Default)]
+pub struct QrParams {
+    /// `png` (default), `svg` or `text`.
+    pub format: Option<String>,
+    /// Pixels per module (PNG) or user units per module (SVG).
+    pub module_size: Option<String>,
+    /// Width of the blank border, in modules.
+    pub quiet_zone: Option<String>,
+    /// `rrggbb` hex color of the dark modules, with or without `#`.
+    pub fg: Option<String>,
+    /// `rrggbb` hex color of the background, with or without `#`.
+    pub bg: Option<String>,
+    /// Error correction level: `L`, `M` (default), `Q` or `H`.
+    pub ec: Option<String>,
+}
+
+/// Validated rendering options.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QrOptions {
+    pub format: QrFormat,
+    pub module_size: u32,
+    pub quiet_zone: u32,
+    pub fg: [u8; 3],
+    pub bg: [u8; 3],
+    pub ec: QrEcLevel,
+}
+
+impl Default for QrOptions {
+    fn default() -> Self {
+        QrOptions {
+            format: QrFormat::Png,
+            module_size: 8,
+            quiet_zone: 4,
+            fg: [0, 0, 0],
+            bg: [255, 255, 255],
+            ec: QrEcLevel::M,
+        }
+    }
+}
+
+fn parse_count(name: &str, value: &Option<String>, default: u32) -> Result<u32, ApiError> {
+    match value {
+        Some(value) => value.parse().map_err(|_| {
+            ApiError::BadRequest(format!("{} must be a whole number, got `{}`", name, value))
+        }),
+        None => Ok(default),
+    }
+}
+
+fn parse_color(name: &str, value: &str) -> Result<[u8; 3], ApiError> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    let invalid = || {
+        ApiError::BadRequest(format!(
+            "{} must be a hex color like 1a2b3c, got `{}`",
+            name, value
+        ))
+    };
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+impl QrParams {
+    pub fn resolve(&self) -> Result<QrOptions, ApiError> {
+        let defaults = QrOptions::default();
+        let format = match &self.format {
+            Some(format) => QrFormat::parse(format)?,
+            None => QrFormat::default(),
+        };
+        if format == QrFormat::Text {
+            // Terminal output has fixed cells and the terminal's own colors.
+            if self.module_size.is_some() || self.fg.is_some() || self.bg.is_some() {
+                return Err(ApiError::BadRequest(
+                    "module_size, fg and bg don't apply to the text format".to_string(),
+                ));
+            }
+        }
+        let module_size = parse_count("module_size", &self.module_size, defaults.module_size)?;
+        if !(1..=MAX_MODULE_SIZE).contains(&module_size) {
+            return Err(ApiError::BadRequest(format!(
+                "module_size must be between 1 and {}",
+                MAX_MODULE_SIZE
+            )));
+        }
+        let quiet_zone = parse_count("quiet_zone", &self.quiet_zone, defaults.quiet_zone)?;
+        if quiet_zone > MAX_QUIET_ZONE {
+            return Err(ApiError::BadRequest(format!(
+                "quiet_zone can be at most {} modules",
+                MAX_QUIET_ZONE
+            )));
+        }
+        let fg = match &self.fg {
+            Some(fg) => parse_color("fg", fg)?,
+            None => defaults.fg,
+        };
+        let bg = match &self.bg {
+            Some(bg) => parse_color("bg", bg)?,
+            None => defaults.bg,
+        };
+        if fg == bg {
+            return Err(ApiError::BadRequest(
+                "fg and bg must differ or the code can't be scanned".to_string(),
+            ));
+        }
+        Ok(QrOptions {
+            format,
+            module_size,
+            quiet_zone,
+            fg,
+            bg,
+            ec: match &self.ec {
+                Some(ec) => QrEcLevel::parse(ec)?,
+                None => QrEcLevel::default(),
+            },
+        })
+    }
+}
+
+pub fn encode(payload: &str, ec: QrEcLevel) -> Result<QrCode, ApiError> {
+    QrCode::with_error_correction_level(payload.as_bytes(), ec.into())
+        .map_err(|e| ApiError::BadRequest(format!("Can't encode the QR code: {}", e)))
+}
+
+/// The dark modules of `qr` as horizontal runs `(x, y, length)`, so vector
+/// output needs one rectangle per run rather than per module.
+pub fn dark_runs(qr: &QrCode) -> Vec<(u32, u32, u32)> {
+    let width = qr.width() as u32;
+    let colors = qr.to_colors();
+    let is_dark = |x: u32, y: u32| colors[(y * width + x) as usize] == Color::Dark;
+    let mut runs = Vec::new();
+    for y in 0..width {
+        let mut x = 0;
+        while x < width {
+            if !is_dark(x, y) {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < width && is_dark(x, y) {
+                x += 1;
+            }
+            runs.push((start, y, x - start));
+        }
+    }
+    runs
+}
+
+/// Renders `payload` as a QR code and returns it with its content type.
+pub fn render(payload: &str, options: &QrOptions) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let qr = encode(payload, options.ec)?;
+    let width = qr.width() as u32;
+    let colors = qr.to_colors();
+    let is_dark = |x: u32, y: u32| colors[(y * width + x) as usize] == Color::Dark;
+    let q = options.quiet_zone;
+    let side = width + 2 * q;
+
+    match options.format {
+        QrFormat::Png => {
+            let pixels = side * options.module_size;
+            if pixels > MAX_PNG_SIDE {
+                return Err(ApiError::BadRequest(format!(
+                    "The image would be {}px wide; lower module_size or quiet_zone to stay within {}px",
+                    pixels, MAX_PNG_SIDE
+                )));
+            }
+            let img = RgbImage::from_fn(pixels, pixels, |px, py| {
+                let (x, y) = (px / options.module_size, py / options.module_size);
+                let inside = (q..q + width).contains(&x) && (q..q + width).contains(&y);
+                match inside && is_dark(x - q, y - q) {
+                    true => Rgb(options.fg),
+                    false => Rgb(options.bg),
+                }
+            });
+            let mut png = Vec::new();
+            img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                .map_err(|e| ApiError::Internal(e.to_string()))?;
+            Ok((ContentType::PNG, png))
+        }
+        QrFormat::Svg => {
+            let hex = |c: [u8; 3]| format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2]);
+            let size = side * options.module_size;
+            let mut path = String::new();
+            for (x, y, run) in dark_runs(&qr) {
+                path += &format!("M{} {}h{}v1h-{}z", x + q, y + q, run, run);
+            }
+            let svg = format!(
+                concat!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                    r#"<svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="{size}" height="{size}" viewBox="0 0 {side} {side}" shape-rendering="crispEdges">"#,
+                    r#"<rect width="{side}" height="{side}" fill="{bg}"/>"#,
+                    r#"<path d="{path}" fill="{fg}"/></svg>"#
+                ),
+                size = size,
+                side = side,
+                bg = hex(options.bg),
+                fg = hex(options.fg),
+                path = path
+            );
+            Ok((ContentType::SVG, svg.into_bytes()))
+        }
+        QrFormat::Text => {
+            // Two module rows per line, drawn with half blocks.
+            let dark = |x: u32, y: u32| {
+                (q..q + width).contains(&x) && (q..q + width).contains(&y) && is_dark(x - q, y - q)
+            };
+            let mut text = String::new();
+            for y in (0..side).step_by(2) {
+                for x in 0..side {
+                    text.push(match (dark(x, y), y + 1 < side && dark(x, y + 1)) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    });
+                }
+                text.push('\n');
+            }
+            Ok((ContentType::Plain, text.into_bytes()))
+        }
+    }
+}
+
+/// `render` off the async workers, since encoding a large PNG takes a while.
+async fn render_blocking(
+    payload: &str,
+    options: &QrOptions,
+) -> Result<(ContentType, Vec<u8>), ApiError> {
+    let (payload, options) = (payload.to_string(), options.clone());
+    tokio::task::spawn_blocking(move || render(&payload, &options))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+}
+
+/// Rendered images keyed by payload and rendering options. When full, the
+/// oldest entry makes room.
+pub struct QrCache {
+    capacity: usize,
+    entries: Mutex<CacheEntries>,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    images: HashMap<String, (ContentType, Vec<u8>)>,
+    /// Keys in insertion order, oldest first.
+    order: VecDeque<String>,
+}
+
+impl QrCache {
+    pub fn new(capacity: usize) -> Self {
+        QrCache {
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Returns the cached image for `payload` and `options`, rendering it on
+    /// a miss.
+    pub async fn get(
+        &self,
+        payload: &str,
+        options: &QrOptions,
+    ) -> Result<(ContentType, Vec<u8>), ApiError> {
+        if self.capacity == 0 {
+            return render_blocking(payload, options).await;
+        }
+        let key = format!("{:?}|{}", options, payload);
+        if let Some(image) = self.entries.lock().unwrap().images.get(&key) {
+            return Ok(image.clone());
+        }
+        let image = render_blocking(payload, options).await?;
+        let mut entries = self.entries.lock().unwrap();
+        if entries.images.insert(key.clone(), image.clone()).is_none() {
+            entries.order.push_back(key);
+            while entries.order.len() > self.capacity {
+                if let Some(oldest) = entries.order.pop_front() {
+                    entries.images.remove(&oldest);
+                }
+            }
+        }
+        Ok(image)
+    }
+}
+
+/// Reads and validates the `qr` settings, and manages them along with the
+/// image cache. Refuses to start on a bad base URL.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("QR codes", |rocket| async {
+        let mut config = match rocket.figment().extract_inner::<QrConfig>("qr") {
+            Ok(config) => config,
+            Err(e) if e.missing() => QrConfig::default(),
+            Err(e) => {
+                error!("Invalid qr settings: {}", e);
+                return Err(rocket);
+            }
+        };
+        if let Err(e) = config.validate() {
+            error!("{}", e);
+            return Err(rocket);
+        }
+        info!("QR codes link to {}<slug>", config.base_url);
+        let cache = QrCache::new(config.cache_size);
+        Ok(rocket.manage(config).manage(cache))
+    })
+}
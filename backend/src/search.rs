@@ -0,0 +1,383 @@
+use deadpool_postgres::Pool;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+use serde_json::{Map, Value};
+use tokio_postgres::Client;
+
+use crate::auth::User;
+use crate::error::ApiError;
+use crate::filter::{Conditions, VehicleFilter};
+use crate::{db, Vehicle, VEHICLE_COLUMNS};
+
+const DEFAULT_MIN_SIMILARITY: f32 = 0.3;
+const DEFAULT_FACET_LIMIT: i64 = 20;
+const DEFAULT_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 100;
+const MAX_FACET_LIMIT: i64 = 500;
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub vehicle: Vehicle,
+    /// Trigram word similarity between the query and the vehicle, 0 to 1.
+    pub score: f32,
+}
+
+/// Typo-tolerant search over manufacturer, model, vehicle type and spec
+/// values. Only vehicles scoring at least `min_similarity` are returned,
+/// best match first.
+#[get("/api/vehicles/search/<query>?<limit>&<min_similarity>")]
+pub async fn fuzzy_search_vehicles(
+    pool: &State<Pool>,
+    _user: User,
+    query: &str,
+    limit: Option<i64>,
+    min_similarity: Option<f32>,
+) -> Result<Json<Vec<SearchHit>>, ApiError> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+    let min_similarity = check_min_similarity(min_similarity)?;
+    let mut client = db::client(pool).await?;
+    fuzzy_search_from_db(&mut client, query, limit, min_similarity)
+        .await
+        .map(Json)
+}
+
+fn check_min_similarity(min_similarity: Option<f32>) -> Result<f32, ApiError> {
+    let min_similarity = min_similarity.unwrap_or(DEFAULT_MIN_SIMILARITY);
+    match (0.0..=1.0).contains(&min_similarity) {
+        true => Ok(min_similarity),
+        false => Err(ApiError::BadRequest(
+            "min_similarity must be between 0 and 1".to_string(),
+        )),
+    }
+}
+
+/// Restricts `conditions` to the vehicles the fuzzy search returns for
+/// `query`, so other listings can follow it.
+pub fn fuzzy_search_condition(
+    query: &str,
+    min_similarity: Option<f32>,
+    conditions: &mut Conditions,
+) -> Result<(), ApiError> {
+    let min_similarity = check_min_similarity(min_similarity)?;
+    let query = conditions.param(query.to_string());
+    let min_similarity = conditions.param(min_similarity);
+    conditions.push(format!(
+        "word_similarity(lower({}), vehicle_search_document(manufacturer, model, vehicle_type, data)) >= {}",
+        query, min_similarity
+    ));
+    Ok(())
+}
+
+/// Finds candidates with `<%`, which the trigram index on
+/// `vehicle_search_document` answers, so only those are scored. The operator
+/// compares against `pg_trgm.word_similarity_threshold`, set for this
+/// transaction only so it never leaks to the next user of the connection.
+async fn fuzzy_search_from_db(
+    client: &mut Client,
+    query: &str,
+    limit: i64,
+    min_similarity: f32,
+) -> Result<Vec<SearchHit>, ApiError> {
+    let transaction = client.transaction().await?;
+    transaction
+        .execute(
+            "SELECT set_config('pg_trgm.word_similarity_threshold', $1, true)",
+            &[&min_similarity.to_string()],
+        )
+        .await?;
+    let hits = transaction
+        .query(
+            &format!(
+                "SELECT {}, word_similarity(lower($1), vehicle_search_document(manufacturer, model, vehicle_type, data)) AS score
+                FROM vehicles
+                WHERE deleted_at IS NULL
+                    AND lower($1) <% vehicle_search_document(manufacturer, model, vehicle_type, data)
+                ORDER BY score DESC, id ASC
+                LIMIT $2",
+                VEHICLE_COLUMNS
+            ),
+            &[&query, &limit],
+        )
+        .await?
+        .iter()
+        .map(|row| SearchHit {
+            vehicle: Vehicle::from_row(row),
+            score: row.get("score"),
+        })
+        .collect::<Vec<SearchHit>>();
+    transaction.commit().await?;
+    Ok(hits)
+}
+
+/// Options passed to `ts_headline` for highlighted snippets.
+const HEADLINE_OPTIONS: &str =
+    "StartSel=<mark>, StopSel=</mark>, MaxWords=20, MinWords=5, MaxFragments=2, FragmentDelimiter=\" ... \"";
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TextSearchHit {
+    #[serde(flatten)]
+    pub vehicle: Vehicle,
+    /// `ts_rank` of the vehicle for the query; higher is better.
+    pub score: f32,
+    /// Highlighted snippet per matching field. Spec values are keyed as
+    /// `spec.<key>`.
+    pub highlights: Map<String, Value>,
+}
+
+/// One piece of a full-text query.
+#[derive(Debug, PartialEq)]
+enum Term {
+    Word(String),
+    /// `word*`: matches every lexeme starting with `word`.
+    Prefix(String),
+    /// `"two words"`: the words must appear next to each other.
+    Phrase(String),
+}
+
+fn parse_text_query(query: &str) -> Vec<Term> {
+    let mut terms = Vec::new();
+    for (i, part) in query.split('"').enumerate() {
+        // Odd-numbered parts sit between a pair of quotes.
+        if i % 2 == 1 {
+            if !part.trim().is_empty() {
+                terms.push(Term::Phrase(part.trim().to_string()));
+            }
+            continue;
+        }
+        for word in part.split_whitespace() {
+            match word.strip_suffix('*') {
+                Some(prefix) => {
+                    let prefix = prefix
+                        .chars()
+                        .filter(|c| c.is_alphanumeric())
+                        .collect::<String>();
+                    if !prefix.is_empty() {
+                        terms.push(Term::Prefix(prefix));
+                    }
+                }
+                None => terms.push(Term::Word(word.to_string())),
+            }
+        }
+    }
+    terms
+}
+
+/// Turns the parsed terms into a `tsquery` expression, binding each term as a
+/// parameter. All terms must match.
+fn tsquery_sql(terms: &[Term], conditions: &mut Conditions) -> String {
+    terms
+        .iter()
+        .map(|term| match term {
+            Term::Word(word) => format!(
+                "plainto_tsquery('english', {}::TEXT)",
+                conditions.param(word.clone())
+            ),
+            Term::Prefix(prefix) => format!(
+                "to_tsquery('english', quote_literal({}::TEXT) || ':*')",
+                conditions.param(prefix.clone())
+            ),
+            Term::Phrase(phrase) => format!(
+                "phraseto_tsquery('english', {}::TEXT)",
+                conditions.param(phrase.clone())
+            ),
+        })
+        .collect::<Vec<String>>()
+        .join(" && ")
+}
+
+/// Restricts `conditions` to vehicles matching the full-text query `q`, with
+/// the same syntax as `/api/vehicles/text-search`.
+pub fn text_search_condition(q: &str, conditions: &mut Conditions) -> Result<(), ApiError> {
+    let terms = parse_text_query(q);
+    if terms.is_empty() {
+        return Err(ApiError::BadRequest("Search query is empty".to_string()));
+    }
+    let tsquery = tsquery_sql(&terms, conditions);
+    conditions.push(format!("search_vector @@ ({})", tsquery));
+    Ok(())
+}
+
+/// Keyword search over the maintained `search_vector` index. Supports
+/// `"quoted phrases"` and `prefix*` terms; every term has to match.
+#[get("/api/vehicles/text-search?<q>&<limit>")]
+pub async fn text_search_vehicles(
+    pool: &State<Pool>,
+    _user: User,
+    q: &str,
+    limit: Option<i64>,
+) -> Result<Json<Vec<TextSearchHit>>, ApiError> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+    let terms = parse_text_query(q);
+    if terms.is_empty() {
+        return Err(ApiError::BadRequest("Search query is empty".to_string()));
+    }
+    let client = db::client(pool).await?;
+    text_search_from_db(&client, &terms, limit).await.map(Json)
+}
+
+async fn text_search_from_db(
+    client: &Client,
+    terms: &[Term],
+    limit: i64,
+) -> Result<Vec<TextSearchHit>, ApiError> {
+    let mut conditions = Conditions::default();
+    let tsquery = tsquery_sql(terms, &mut conditions);
+    let options = conditions.param(HEADLINE_OPTIONS.to_string());
+    let limit = conditions.param(limit);
+    let hits = client
+        .query(
+            &format!(
+                "SELECT {columns}, ts_rank(search_vector, query.q) AS score,
+                    (SELECT coalesce(jsonb_object_agg(field, snippet), '{{}}'::JSONB) FROM (
+                        SELECT field, ts_headline('english', value, query.q, {options}::TEXT) AS snippet
+                        FROM (VALUES ('manufacturer', manufacturer), ('model', model), ('vehicle_type', vehicle_type)) fields(field, value)
+                        UNION ALL
+                        SELECT 'spec.' || key, ts_headline('english', value, query.q, {options}::TEXT)
+                        FROM jsonb_each_text(data)
+                    ) snippets WHERE snippet LIKE '%<mark>%') AS highlights
+                FROM vehicles, (SELECT {tsquery} AS q) query
+                WHERE search_vector @@ query.q AND deleted_at IS NULL
+                ORDER BY score DESC, id ASC
+                LIMIT {limit}",
+                columns = VEHICLE_COLUMNS,
+            ),
+            &conditions.params(),
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            let highlights: Value = row.get("highlights");
+            TextSearchHit {
+                vehicle: Vehicle::from_row(row),
+                score: row.get("score"),
+                highlights: match highlights {
+                    Value::Object(map) => map,
+                    _ => Map::new(),
+                },
+            }
+        })
+        .collect::<Vec<TextSearchHit>>();
+    Ok(hits)
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Facet {
+    pub value: String,
+    pub count: i64,
+}
+
+/// Distinct values of a column, most common first. Accepts the same
+/// `prefix`, `limit` and scoping filters as the facet route.
+#[get("/api/vehicles/search/unique/<column>?<prefix>&<limit>&<filter..>")]
+pub async fn search_unique_cols_vehicles(
+    pool: &State<Pool>,
+    _user: User,
+    column: &str,
+    prefix: Option<&str>,
+    limit: Option<i64>,
+    filter: VehicleFilter,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let client = db::client(pool).await?;
+    let facets = facets_from_db(&client, column, prefix, limit, &filter).await?;
+    Ok(Json(facets.into_iter().map(|f| f.value).collect()))
+}
+
+/// Distinct values of a column with how many vehicles carry each one.
+/// `prefix` keeps values starting with it (case-insensitive), and the listing
+/// filters scope the counts, e.g. `/api/vehicles/facets/model?manufacturer=Toyota`.
+#[get("/api/vehicles/facets/<column>?<prefix>&<limit>&<filter..>")]
+pub async fn facet_vehicles(
+    pool: &State<Pool>,
+    _user: User,
+    column: &str,
+    prefix: Option<&str>,
+    limit: Option<i64>,
+    filter: VehicleFilter,
+) -> Result<Json<Vec<Facet>>, ApiError> {
+    let limit = Some(limit.unwrap_or(DEFAULT_FACET_LIMIT));
+    let client = db::client(pool).await?;
+    facets_from_db(&client, column, prefix, limit, &filter)
+        .await
+        .map(Json)
+}
+
+async fn facets_from_db(
+    client: &Client,
+    col_name: &str,
+    prefix: Option<&str>,
+    limit: Option<i64>,
+    filter: &VehicleFilter,
+) -> Result<Vec<Facet>, ApiError> {
+    let column = match col_name {
+        c if c == "vehicle_type" || c == "manufacturer" || c == "model" => c,
+        "data" => "data::TEXT",
+        "price" => "price_amount::TEXT",
+        "currency" => "price_currency::TEXT",
+        _ => {
+            return Err(ApiError::BadRequest(
+                "Unmatched column name given".to_string(),
+            ));
+        }
+    };
+    if limit.is_some_and(|l| !(1..=MAX_FACET_LIMIT).contains(&l)) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_FACET_LIMIT
+        )));
+    }
+
+    let mut conditions = Conditions::default();
+    conditions.push("deleted_at IS NULL".to_string());
+    filter.apply(&mut conditions)?;
+    conditions.push(format!("{} IS NOT NULL", column));
+    if let Some(prefix) = prefix.filter(|p| !p.is_empty()) {
+        let pattern = conditions.param(format!("{}%", escape_like(prefix)));
+        conditions.push(format!("{} ILIKE {}", column, pattern));
+    }
+    let limit = match limit {
+        Some(limit) => format!("LIMIT {}", conditions.param(limit)),
+        None => String::new(),
+    };
+
+    let facets = client
+        .query(
+            &format!(
+                "SELECT {column} AS value, COUNT(*) AS count FROM vehicles {where_clause}
+                GROUP BY 1 ORDER BY count DESC, value ASC {limit}",
+                where_clause = conditions.where_clause(),
+            ),
+            &conditions.params(),
+        )
+        .await?
+        .iter()
+        .map(|row| Facet {
+            value: row.get("value"),
+            count: row.get("count"),
+        })
+        .collect::<Vec<Facet>>();
+    Ok(facets)
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use deadpool_postgres::Pool;
+use rocket::fairing::AdHoc;
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+use tokio_postgres::Client;
+
+use crate::auth::{Editor, User};
+use crate::error::ApiError;
+use crate::etag::{ETag, Tagged};
+use crate::filter::Paging;
+use crate::{db, Vehicle, VehiclePage, VEHICLE_COLUMNS};
+
+/// Settings read from the `trash` table of the active Rocket profile.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TrashConfig {
+    /// Days a deleted vehicle stays restorable before it is purged.
+    #[serde(default = "default_retention_days")]
+    pub retention_days: i32,
+    /// Seconds between purge runs.
+    #[serde(default = "default_purge_interval")]
+    pub purge_interval: u64,
+}
+
+fn default_retention_days() -> i32 {
+    30
+}
+
+fn default_purge_interval() -> u64 {
+    3600
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            retention_days: default_retention_days(),
+            purge_interval: default_purge_interval(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TrashedVehicle {
+    #[serde(flatten)]
+    pub vehicle: Vehicle,
+    pub deleted_at: String,
+    pub deleted_by: Option<String>,
+}
+
+/// Deleted vehicles that can still be restored, most recently deleted first.
+#[get("/api/vehicles/trash?<limit>&<offset>")]
+pub async fn get_trash(
+    pool: &State<Pool>,
+    _user: User,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Json<VehiclePage<TrashedVehicle>>, ApiError> {
+    let paging = Paging::new(limit, offset, None)?;
+    let client = db::client(pool).await?;
+    let total: i64 = client
+        .query_one(
+            "SELECT COUNT(*) FROM vehicles WHERE deleted_at IS NOT NULL",
+            &[],
+        )
+        .await?
+        .get(0);
+    let items = client
+        .query(
+            &format!(
+                "SELECT {}, deleted_at::TEXT, deleted_by FROM vehicles
+                WHERE deleted_at IS NOT NULL
+                ORDER BY deleted_at DESC, id ASC
+                LIMIT $1 OFFSET $2",
+                VEHICLE_COLUMNS
+            ),
+            &[&paging.limit, &paging.offset],
+        )
+        .await?
+        .iter()
+        .map(|row| TrashedVehicle {
+            vehicle: Vehicle::from_row(row),
+            deleted_at: row.get(9),
+            deleted_by: row.get(10),
+        })
+        .collect::<Vec<TrashedVehicle>>();
+    Ok(Json(VehiclePage {
+        items,
+        total,
+        limit: paging.limit,
+        offset: paging.offset,
+        next_offset: paging.next_offset(total),
+    }))
+}
+
+/// Takes a vehicle back out of the trash.
+#[post("/api/vehicles/<id>/restore")]
+pub async fn restore_vehicle(
+    pool: &State<Pool>,
+    id: i32,
+    user: Editor,
+) -> Result<Tagged<Json<Vehicle>>, ApiError> {
+    let mut client = db::client(pool).await?;
+    let transaction = db::audited(&mut client, &user).await?;
+    let row = transaction
+        .query_opt(
+            &format!(
+                "UPDATE vehicles SET deleted_at = NULL, deleted_by = NULL
+                WHERE id = $1 AND deleted_at IS NOT NULL
+                RETURNING {}",
+                VEHICLE_COLUMNS
+            ),
+            &[&id],
+        )
+        .await?
+        .ok_or_else(|| ApiError::NotFound("No deleted vehicle with this ID".to_string()))?;
+    transaction.commit().await?;
+    let vehicle = Vehicle::from_row(&row);
+    Ok(Tagged {
+        etag: ETag(vehicle.version),
+        inner: Json(vehicle),
+    })
+}
+
+/// Permanently removes vehicles that have been in the trash longer than the
+/// retention period. Returns how many were removed.
+pub async fn purge(client: &Client, retention_days: i32) -> Result<u64, ApiError> {
+    Ok(client
+        .execute(
+            "DELETE FROM vehicles
+            WHERE deleted_at IS NOT NULL AND deleted_at < now() - make_interval(days => $1)",
+            &[&retention_days],
+        )
+        .await?)
+}
+
+async fn purge_loop(pool: Pool, config: TrashConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.purge_interval));
+    loop {
+        interval.tick().await;
+        let result = match db::client(&pool).await {
+            Ok(client) => purge(&client, config.retention_days).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(0) => {}
+            Ok(purged) => info!("Purged {} vehicle(s) from the trash", purged),
+            Err(e) => warn!("Failed to purge the trash: {}", e.message()),
+        }
+    }
+}
+
+/// Checks the `trash` settings before launch and starts the background
+/// purge once the server is up.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Trash purge", |rocket| async {
+        let config = match rocket.figment().extract_inner::<TrashConfig>("trash") {
+            Ok(config) => config,
+            Err(e) if e.missing() => TrashConfig::default(),
+            Err(e) => {
+                error!("Invalid trash settings: {}", e);
+                return Err(rocket);
+            }
+        };
+        if config.retention_days < 0 || config.purge_interval == 0 {
+            error!("trash.retention_days must be >= 0 and trash.purge_interval > 0");
+            return Err(rocket);
+        }
+        Ok(rocket.manage(config).attach(AdHoc::on_liftoff("Trash purge loop", |rocket| {
+            Box::pin(async move {
+                if let (Some(pool), Some(config)) =
+                    (rocket.state::<Pool>(), rocket.state::<TrashConfig>())
+                {
+                    tokio::spawn(purge_loop(pool.clone(), config.clone()));
+                }
+            })
+        })))
+    })
+}
@@ -0,0 +1,136 @@
+use deadpool_postgres::Pool;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+
+use crate::auth::{hash_password, role_from_db, Admin, Role};
+use crate::db;
+use crate::error::ApiError;
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Account {
+    pub id: i32,
+    pub username: String,
+    pub role: Role,
+    pub created_at: String,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NewAccount {
+    pub username: String,
+    pub password: String,
+    /// Defaults to viewer.
+    pub role: Option<Role>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RoleChange {
+    pub role: Role,
+}
+
+#[get("/api/users")]
+pub async fn get_users(pool: &State<Pool>, _user: Admin) -> Result<Json<Vec<Account>>, ApiError> {
+    let client = db::client(pool).await?;
+    let users = client
+        .query(
+            "SELECT id, username, role, created_at::TEXT FROM users ORDER BY username",
+            &[],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            Ok(Account {
+                id: row.get(0),
+                username: row.get(1),
+                role: role_from_db(row.get(2))?,
+                created_at: row.get(3),
+            })
+        })
+        .collect::<Result<_, ApiError>>()?;
+    Ok(Json(users))
+}
+
+/// Creates an account that can sign in with the given password.
+#[post("/api/users", data = "<account>")]
+pub async fn create_user(
+    pool: &State<Pool>,
+    _user: Admin,
+    account: Json<NewAccount>,
+) -> Result<Json<Account>, ApiError> {
+    let NewAccount {
+        username,
+        password,
+        role,
+    } = account.into_inner();
+    let username = username.trim().to_string();
+    if username.is_empty() {
+        return Err(ApiError::Validation("Username is required".to_string()));
+    }
+    let role = role.unwrap_or(Role::Viewer);
+    let password_hash = hash_password(password).await?;
+    let client = db::client(pool).await?;
+    let row = client
+        .query_one(
+            "INSERT INTO users (username, password_hash, role) VALUES ($1, $2, $3)
+            RETURNING id, created_at::TEXT",
+            &[&username, &password_hash, &role.as_str()],
+        )
+        .await
+        .map_err(|e| match ApiError::from(e) {
+            ApiError::Conflict(_) => ApiError::Conflict(format!("The username `{}` is taken", username)),
+            e => e,
+        })?;
+    Ok(Json(Account {
+        id: row.get(0),
+        username,
+        role,
+        created_at: row.get(1),
+    }))
+}
+
+/// Gives an account another role. Takes effect on its next request, also
+/// for sessions already open.
+#[put("/api/users/<id>/role", data = "<change>")]
+pub async fn set_user_role(
+    pool: &State<Pool>,
+    user: Admin,
+    id: i32,
+    change: Json<RoleChange>,
+) -> Result<Json<Account>, ApiError> {
+    if id == user.id {
+        // Keeps at least one admin around.
+        return Err(ApiError::Conflict("You can't change your own role".to_string()));
+    }
+    let client = db::client(pool).await?;
+    let row = client
+        .query_opt(
+            "UPDATE users SET role = $2 WHERE id = $1 RETURNING username, created_at::TEXT",
+            &[&id, &change.role.as_str()],
+        )
+        .await?
+        .ok_or_else(|| ApiError::NotFound("No user with this ID".to_string()))?;
+    Ok(Json(Account {
+        id,
+        username: row.get(0),
+        role: change.role,
+        created_at: row.get(1),
+    }))
+}
+
+/// Deletes an account along with its sessions and API tokens. The history
+/// keeps its username.
+#[delete("/api/users/<id>")]
+pub async fn delete_user(pool: &State<Pool>, user: Admin, id: i32) -> Result<Status, ApiError> {
+    if id == user.id {
+        return Err(ApiError::Conflict("You can't delete your own account".to_string()));
+    }
+    let client = db::client(pool).await?;
+    match client.execute("DELETE FROM users WHERE id = $1", &[&id]).await? {
+        0 => Err(ApiError::NotFound("No user with this ID".to_string())),
+        _ => Ok(Status::NoContent),
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+
+use deadpool_postgres::Pool;
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+use serde_json::{Map, Value};
+use tokio_postgres::Client;
+
+use crate::auth::{Admin, User};
+use crate::error::ApiError;
+use crate::filter::is_spec_key;
+use crate::price::Price;
+use crate::{db, Vehicle};
+
+/// Top-level vehicle fields that can be made public. Specification keys
+/// are addressed as `spec.<key>`.
+const FIELDS: [&str; 4] = ["vehicle_type", "manufacturer", "model", "price"];
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct FieldVisibility {
+    pub field: String,
+    pub public: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct VisibilityChange {
+    pub public: bool,
+}
+
+/// Who a vehicle is shown to.
+pub enum Audience {
+    /// Whoever scans a sticker; sees only the fields in the set.
+    Public(HashSet<String>),
+    /// Signed-in users, who see every field.
+    Staff,
+}
+
+impl Audience {
+    fn sees(&self, field: &str) -> bool {
+        match self {
+            Audience::Public(fields) => fields.contains(field),
+            Audience::Staff => true,
+        }
+    }
+}
+
+/// The fields currently marked public.
+pub async fn public_fields(client: &Client) -> Result<HashSet<String>, ApiError> {
+    Ok(client
+        .query("SELECT field FROM field_visibility WHERE public", &[])
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect())
+}
+
+/// A vehicle as shown on the QR page. Fields the audience may not see are
+/// left out rather than sent empty.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PublicVehicle {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vehicle_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<Price>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price_legacy: Option<String>,
+    /// Only the specification keys the audience may see.
+    pub data: Map<String, Value>,
+}
+
+impl PublicVehicle {
+    pub fn project(vehicle: Vehicle, audience: &Audience) -> Self {
+        let staff = matches!(audience, Audience::Staff);
+        let show_price = audience.sees("price");
+        let data = match vehicle.data {
+            Value::Object(specs) => specs
+                .into_iter()
+                .filter(|(key, _)| audience.sees(&format!("spec.{}", key)))
+                .collect(),
+            _ => Map::new(),
+        };
+        PublicVehicle {
+            // Ids are sequential, so they'd let anyone enumerate vehicles.
+            id: vehicle.id.filter(|_| staff),
+            vehicle_type: Some(vehicle.vehicle_type).filter(|_| audience.sees("vehicle_type")),
+            manufacturer: Some(vehicle.manufacturer).filter(|_| audience.sees("manufacturer")),
+            model: Some(vehicle.model).filter(|_| audience.sees("model")),
+            price: vehicle.price.filter(|_| show_price),
+            price_legacy: vehicle.price_legacy.filter(|_| show_price),
+            data,
+        }
+    }
+}
+
+fn validate_field(field: &str) -> Result<(), ApiError> {
+    let known = FIELDS.contains(&field) || field.strip_prefix("spec.").is_some_and(is_spec_key);
+    match known {
+        true => Ok(()),
+        false => Err(ApiError::BadRequest(format!(
+            "Unknown field `{}`; use one of {} or spec.<key>",
+            field,
+            FIELDS.join(", ")
+        ))),
+    }
+}
+
+/// Every visibility setting. Fields that aren't listed are internal.
+#[get("/api/visibility")]
+pub async fn get_visibility(
+    pool: &State<Pool>,
+    _user: User,
+) -> Result<Json<Vec<FieldVisibility>>, ApiError> {
+    let client = db::client(pool).await?;
+    let settings = client
+        .query("SELECT field, public FROM field_visibility ORDER BY field", &[])
+        .await?
+        .iter()
+        .map(|row| FieldVisibility {
+            field: row.get(0),
+            public: row.get(1),
+        })
+        .collect();
+    Ok(Json(settings))
+}
+
+/// Makes a field public or internal on the QR page.
+#[put("/api/visibility/<field>", data = "<change>")]
+pub async fn set_visibility(
+    pool: &State<Pool>,
+    _user: Admin,
+    field: &str,
+    change: Json<VisibilityChange>,
+) -> Result<Json<FieldVisibility>, ApiError> {
+    validate_field(field)?;
+    let client = db::client(pool).await?;
+    client
+        .execute(
+            "INSERT INTO field_visibility (field, public) VALUES ($1, $2)
+            ON CONFLICT (field) DO UPDATE SET public = EXCLUDED.public",
+            &[&field, &change.public],
+        )
+        .await?;
+    Ok(Json(FieldVisibility {
+        field: field.to_string(),
+        public: change.public,
+    }))
+}
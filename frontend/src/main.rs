@@ -1,81 +1,137 @@
+use std::collections::BTreeMap;
+
 use base64::engine::{general_purpose::STANDARD, Engine as _};
-use gloo::net::http::Request;
+use gloo::file::{Blob, ObjectUrl};
+use gloo::net::http::{Request, Response};
+use gloo::storage::{LocalStorage, Storage};
+use gloo::timers::callback::Timeout;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 const IP_ADDR: &str = "192.168.1.20"; //"127.0.0.1";
 const PORT: &str = "8000";
+const PAGE_SIZE: i64 = 20;
+/// Local storage key of the signed-in session.
+const SESSION_KEY: &str = "session";
 
 #[derive(Clone, Routable, PartialEq)]
 enum Route {
     #[at("/")]
     Home,
+    #[at("/login")]
+    Login,
     #[at("/:id")]
     Info { id: String },
+    /// Where QR codes lead: the vehicle behind a public slug.
+    #[at("/v/:slug")]
+    Public { slug: String },
 }
 
 #[function_component(App)]
 fn main_app() -> Html {
-    let vehicle_state = use_state(|| {
-        (
-            "".to_string(),
-            "".to_string(),
-            "".to_string(),
-            "".to_string(),
-            "".to_string(),
-            None as Option<i32>,
-        )
-    });
+    let vehicle_state = use_state(VehicleForm::default);
     let message = use_state(|| "".to_string());
     let search_text = use_state(|| "".to_string());
+    // The search whose results the list shows; `None` for a plain page.
+    let shown_search = use_state(|| None as Option<String>);
     let vehicles = use_state(Vec::new);
     let suggestion_list = use_state(Vec::new);
     let qr_img = use_state(|| "".to_string());
+    let export_format = use_state(|| "csv".to_string());
+    let page = use_state(|| None as Option<VehiclePage>);
+    // `None` while the trash is hidden.
+    let trash = use_state(|| None as Option<Vec<TrashedVehicle>>);
+    let session = use_state(stored_session);
 
-    let get_vehicles = {
-        let vehicles = vehicles.clone();
+    let logout = {
+        let session = session.clone();
         let message = message.clone();
 
         Callback::from(move |_| {
-            let vehicles = vehicles.clone();
+            let session = session.clone();
             let message = message.clone();
+
             spawn_local(async move {
-                match Request::get(&format!("http://{}:{}/api/vehicles", IP_ADDR, PORT))
+                // Forget the login even if the server can't be told, so
+                // this browser stops sending the token.
+                let _ = signed(Request::post(&format!("http://{}:{}/api/auth/logout", IP_ADDR, PORT)))
                     .send()
-                    .await
+                    .await;
+                LocalStorage::delete(SESSION_KEY);
+                session.set(None);
+                message.set("Logged out".into());
+            });
+        })
+    };
+
+    let load_page = {
+        let vehicles = vehicles.clone();
+        let page = page.clone();
+        let shown_search = shown_search.clone();
+        let message = message.clone();
+
+        Callback::from(move |offset: i64| {
+            let vehicles = vehicles.clone();
+            let page = page.clone();
+            let shown_search = shown_search.clone();
+            let message = message.clone();
+            spawn_local(async move {
+                match signed(Request::get(&format!(
+                    "http://{}:{}/api/vehicles?limit={}&offset={}",
+                    IP_ADDR, PORT, PAGE_SIZE, offset
+                )))
+                .send()
+                .await
                 {
-                    Ok(resp) if resp.ok() => {
-                        let fetched_vehicles: Vec<Vehicle> = resp.json().await.unwrap_or_default();
-                        vehicles.set(fetched_vehicles);
-                    }
+                    Ok(resp) if resp.ok() => match resp.json::<VehiclePage>().await {
+                        Ok(fetched_page) => {
+                            vehicles.set(fetched_page.items.clone());
+                            page.set(Some(fetched_page));
+                            shown_search.set(None);
+                        }
+                        Err(_) => message.set("Failed to fetch vehicles".into()),
+                    },
                     _ => message.set("Failed to fetch vehicles".into()),
                 }
             });
         })
     };
 
+    let get_vehicles = {
+        let offset = page.as_ref().map(|p| p.offset).unwrap_or(0);
+        load_page.reform(move |_| offset)
+    };
+
     let fuzzy_search_vehicles = {
         let vehicles = vehicles.clone();
         let search_text = search_text.clone();
+        let shown_search = shown_search.clone();
         let message = message.clone();
 
         Callback::from(move |_| {
             let vehicles = vehicles.clone();
             let search_text = (*search_text).clone();
+            let shown_search = shown_search.clone();
             let message = message.clone();
             spawn_local(async move {
-                match Request::get(&format!(
+                // Typed text can hold `/`, `?` or `#`, which would change
+                // the route.
+                let query = String::from(web_sys::js_sys::encode_uri_component(&search_text));
+                match signed(Request::get(&format!(
                     "http://{}:{}/api/vehicles/search/{}",
-                    IP_ADDR, PORT, search_text
-                ))
+                    IP_ADDR, PORT, query
+                )))
                 .send()
                 .await
                 {
                     Ok(resp) if resp.ok() => {
                         let fetched_vehicles: Vec<Vehicle> = resp.json().await.unwrap_or_default();
                         vehicles.set(fetched_vehicles);
+                        shown_search.set(Some(search_text));
                     }
                     _ => message.set("Failed to fetch vehicles".into()),
                 }
@@ -85,21 +141,34 @@ fn main_app() -> Html {
 
     let search_unique_cols_vehicles = {
         let suggestion_list = suggestion_list.clone();
+        let vehicle_state = vehicle_state.clone();
         let message = message.clone();
 
         Callback::from(move |col_name: String| {
             let suggestion_list = suggestion_list.clone();
             let message = message.clone();
+            // Scope suggestions by what is already typed in the other fields,
+            // so models are only suggested for the chosen manufacturer.
+            let scope = web_sys::UrlSearchParams::new().unwrap();
+            for (field, value) in [
+                ("vehicle_type", &vehicle_state.vehicle_type),
+                ("manufacturer", &vehicle_state.manufacturer),
+            ] {
+                if field != col_name && !value.trim().is_empty() {
+                    scope.append(field, value.trim());
+                }
+            }
+            let scope = String::from(scope.to_string());
             spawn_local(async move {
-                match Request::get(&format!(
-                    "http://{}:{}/api/vehicles/search/unique/{}",
-                    IP_ADDR, PORT, col_name
-                ))
+                match signed(Request::get(&format!(
+                    "http://{}:{}/api/vehicles/facets/{}?{}",
+                    IP_ADDR, PORT, col_name, scope
+                )))
                 .send()
                 .await
                 {
                     Ok(resp) if resp.ok() => {
-                        let fetched_rows: Vec<String> = resp.json().await.unwrap_or_default();
+                        let fetched_rows: Vec<Facet> = resp.json().await.unwrap_or_default();
                         suggestion_list.set(fetched_rows);
                     }
                     _ => message.set("Failed to fetch unique rows from column".into()),
@@ -116,18 +185,21 @@ fn main_app() -> Html {
         let fuzzy_search_vehicles = fuzzy_search_vehicles.clone();
 
         Callback::from(move |_| {
-            let (vehicle_type, manufacturer, model, price, data, _) = (*vehicle_state).clone();
+            let form = (*vehicle_state).clone();
             let vehicle_state = vehicle_state.clone();
             let message = message.clone();
             let search_text = search_text.clone();
             let get_vehicles = get_vehicles.clone();
             let fuzzy_search_vehicles = fuzzy_search_vehicles.clone();
+            let body = match form.to_json() {
+                Ok(body) => body,
+                Err(e) => return message.set(e),
+            };
 
             spawn_local(async move {
-                let vehicle_data = serde_json::json!({"vehicle_type": vehicle_type, "manufacturer": manufacturer, "model": model, "price": price, "data": data});
-                let response = Request::post(&format!("http://{}:{}/api/vehicles", IP_ADDR, PORT))
+                let response = signed(Request::post(&format!("http://{}:{}/api/vehicles", IP_ADDR, PORT)))
                     .header("Content-Type", "application/json")
-                    .body(vehicle_data.to_string())
+                    .body(Value::Object(body).to_string())
                     .send()
                     .await;
                 match response {
@@ -139,16 +211,13 @@ fn main_app() -> Html {
                             fuzzy_search_vehicles.emit(())
                         };
                     }
-                    _ => message.set("Failed to create vehicle".into()),
+                    Ok(resp) => message.set(format!(
+                        "Failed to create vehicle: {}",
+                        api_error(&resp).await.message
+                    )),
+                    Err(_) => message.set("Failed to create vehicle".into()),
                 }
-                vehicle_state.set((
-                    "".to_string(),
-                    "".to_string(),
-                    "".to_string(),
-                    "".to_string(),
-                    "".to_string(),
-                    None,
-                ));
+                vehicle_state.set(VehicleForm::default());
             });
         })
     };
@@ -161,32 +230,29 @@ fn main_app() -> Html {
         let fuzzy_search_vehicles = fuzzy_search_vehicles.clone();
 
         Callback::from(move |_| {
-            let (vehicle_type, manufacturer, model, price, data, edited_vehicle_id) =
-                (*vehicle_state).clone();
+            let form = (*vehicle_state).clone();
             let vehicle_state = vehicle_state.clone();
             let search_text = search_text.clone();
             let message = message.clone();
             let get_vehicles = get_vehicles.clone();
             let fuzzy_search_vehicles = fuzzy_search_vehicles.clone();
+            let body = match form.changes() {
+                Ok(body) => body,
+                Err(e) => return message.set(e),
+            };
+            if body.is_empty() {
+                return message.set("Nothing changed".into());
+            }
 
-            if let Some(id) = edited_vehicle_id {
+            if let Some(id) = form.id {
                 spawn_local(async move {
-                    let response =
-                        Request::put(&format!("http://{}:{}/api/vehicles/{}", IP_ADDR, PORT, id))
-                            .header("Content-Type", "application/json")
-                            .body(
-                                serde_json::to_string(&(
-                                    id,
-                                    vehicle_type.as_str(),
-                                    manufacturer.as_str(),
-                                    model.as_str(),
-                                    price.as_str(),
-                                    data.as_str(),
-                                ))
-                                .unwrap(),
-                            )
-                            .send()
-                            .await;
+                    let mut request =
+                        signed(Request::patch(&format!("http://{}:{}/api/vehicles/{}", IP_ADDR, PORT, id)))
+                            .header("Content-Type", "application/json");
+                    if let Some(version) = form.version {
+                        request = request.header("If-Match", &format!("\"v{}\"", version));
+                    }
+                    let response = request.body(Value::Object(body).to_string()).send().await;
                     match response {
                         Ok(resp) if resp.ok() => {
                             message.set("Vehicle updated successfully".into());
@@ -196,41 +262,133 @@ fn main_app() -> Html {
                                 fuzzy_search_vehicles.emit(())
                             };
                         }
-                        _ => message.set("Failed to update vehicle".into()),
+                        Ok(resp) if resp.status() == 412 => {
+                            // Someone else saved first: show their version
+                            // instead of silently overwriting it.
+                            if let Some(current) = api_error(&resp).await.current {
+                                message.set(
+                                    "This vehicle changed since you opened it. The form now shows the latest values, re-apply your edits and update again".into(),
+                                );
+                                vehicle_state.set(VehicleForm::from(&current));
+                                get_vehicles.emit(());
+                                return;
+                            }
+                            message.set("This vehicle changed since you opened it".into());
+                        }
+                        Ok(resp) => message.set(format!(
+                            "Failed to update vehicle: {}",
+                            api_error(&resp).await.message
+                        )),
+                        Err(_) => message.set("Failed to update vehicle".into()),
                     }
-                    vehicle_state.set((
-                        "".to_string(),
-                        "".to_string(),
-                        "".to_string(),
-                        "".to_string(),
-                        "".to_string(),
-                        None,
-                    ));
+                    vehicle_state.set(VehicleForm::default());
                 });
             }
         })
     };
 
-    let delete_vehicle = {
+    let load_trash = {
+        let trash = trash.clone();
+        let message = message.clone();
+
+        Callback::from(move |_| {
+            let trash = trash.clone();
+            let message = message.clone();
+            spawn_local(async move {
+                match signed(Request::get(&format!("http://{}:{}/api/vehicles/trash", IP_ADDR, PORT)))
+                    .send()
+                    .await
+                {
+                    Ok(resp) if resp.ok() => match resp.json::<TrashPage>().await {
+                        Ok(fetched) => trash.set(Some(fetched.items)),
+                        Err(_) => message.set("Failed to fetch the trash".into()),
+                    },
+                    _ => message.set("Failed to fetch the trash".into()),
+                }
+            });
+        })
+    };
+
+    let toggle_trash = {
+        let trash = trash.clone();
+        let load_trash = load_trash.clone();
+        Callback::from(move |_| match *trash {
+            Some(_) => trash.set(None),
+            None => load_trash.emit(()),
+        })
+    };
+
+    let restore_vehicle = {
         let message = message.clone();
         let get_vehicles = get_vehicles.clone();
+        let load_trash = load_trash.clone();
 
         Callback::from(move |id: i32| {
             let message = message.clone();
             let get_vehicles = get_vehicles.clone();
+            let load_trash = load_trash.clone();
+
+            spawn_local(async move {
+                let response = signed(Request::post(&format!(
+                    "http://{}:{}/api/vehicles/{}/restore",
+                    IP_ADDR, PORT, id
+                )))
+                .send()
+                .await;
+                match response {
+                    Ok(resp) if resp.ok() => {
+                        message.set("Vehicle restored".into());
+                        get_vehicles.emit(());
+                        load_trash.emit(());
+                    }
+                    Ok(resp) => message.set(format!(
+                        "Failed to restore vehicle: {}",
+                        api_error(&resp).await.message
+                    )),
+                    Err(_) => message.set("Failed to restore vehicle".into()),
+                }
+            });
+        })
+    };
+
+    let delete_vehicle = {
+        let message = message.clone();
+        let get_vehicles = get_vehicles.clone();
+        let trash = trash.clone();
+        let load_trash = load_trash.clone();
+
+        Callback::from(move |(id, version): (i32, i32)| {
+            let message = message.clone();
+            let get_vehicles = get_vehicles.clone();
+            let trash_shown = trash.is_some();
+            let load_trash = load_trash.clone();
 
             spawn_local(async move {
                 let response =
-                    Request::delete(&format!("http://{}:{}/api/vehicles/{}", IP_ADDR, PORT, id))
+                    signed(Request::delete(&format!("http://{}:{}/api/vehicles/{}", IP_ADDR, PORT, id)))
+                        .header("If-Match", &format!("\"v{}\"", version))
                         .send()
                         .await;
 
                 match response {
                     Ok(resp) if resp.ok() => {
-                        message.set("Vehicle deleted successfully".into());
+                        message.set("Vehicle moved to the trash".into());
+                        get_vehicles.emit(());
+                        if trash_shown {
+                            load_trash.emit(());
+                        }
+                    }
+                    Ok(resp) if resp.status() == 412 => {
+                        message.set(
+                            "This vehicle changed since the list was loaded, check it before deleting".into(),
+                        );
                         get_vehicles.emit(());
                     }
-                    _ => message.set("Failed to delete vehicle".into()),
+                    Ok(resp) => message.set(format!(
+                        "Failed to delete vehicle: {}",
+                        api_error(&resp).await.message
+                    )),
+                    Err(_) => message.set("Failed to delete vehicle".into()),
                 }
             });
         })
@@ -241,14 +399,7 @@ fn main_app() -> Html {
         let vehicles = vehicles.clone();
         Callback::from(move |id: i32| {
             if let Some(vehicle) = vehicles.iter().find(|v| v.id == id) {
-                vehicle_state.set((
-                    vehicle.vehicle_type.clone(),
-                    vehicle.manufacturer.clone(),
-                    vehicle.model.clone(),
-                    vehicle.price.clone(),
-                    vehicle.data.clone(),
-                    Some(id),
-                ));
+                vehicle_state.set(VehicleForm::from(vehicle));
             };
         })
     };
@@ -262,10 +413,10 @@ fn main_app() -> Html {
             let qr_img = qr_img.clone();
 
             spawn_local(async move {
-                let response = Request::get(&format!(
+                let response = signed(Request::get(&format!(
                     "http://{}:{}/api/vehicles/qr/{}",
                     IP_ADDR, PORT, id
-                ))
+                )))
                 .send()
                 .await;
 
@@ -274,14 +425,89 @@ fn main_app() -> Html {
                         message.set("Successfully got QR code".into());
                         qr_img.set(STANDARD.encode(resp.binary().await.unwrap()));
                     }
-                    _ => message.set("Failed to fetch qr code".into()),
+                    Ok(resp) => message.set(format!(
+                        "Failed to fetch qr code: {}",
+                        api_error(&resp).await.message
+                    )),
+                    Err(_) => message.set("Failed to fetch qr code".into()),
+                }
+            });
+        })
+    };
+
+    // A new code replaces a lost sticker; the old one stops resolving.
+    let regenerate_qr = {
+        let message = message.clone();
+        let show_vehicle_qr = show_vehicle_qr.clone();
+
+        Callback::from(move |id: i32| {
+            let message = message.clone();
+            let show_vehicle_qr = show_vehicle_qr.clone();
+
+            spawn_local(async move {
+                let response = signed(Request::post(&format!(
+                    "http://{}:{}/api/vehicles/{}/slug",
+                    IP_ADDR, PORT, id
+                )))
+                .send()
+                .await;
+                match response {
+                    Ok(resp) if resp.ok() => show_vehicle_qr.emit(id),
+                    Ok(resp) => message.set(format!(
+                        "Failed to generate a new QR code: {}",
+                        api_error(&resp).await.message
+                    )),
+                    Err(_) => message.set("Failed to generate a new QR code".into()),
+                }
+            });
+        })
+    };
+
+    let revoke_qr = {
+        let message = message.clone();
+        let qr_img = qr_img.clone();
+
+        Callback::from(move |id: i32| {
+            let message = message.clone();
+            let qr_img = qr_img.clone();
+
+            spawn_local(async move {
+                let response = signed(Request::delete(&format!(
+                    "http://{}:{}/api/vehicles/{}/slug",
+                    IP_ADDR, PORT, id
+                )))
+                .send()
+                .await;
+                match response {
+                    Ok(resp) if resp.ok() => {
+                        message.set("QR code revoked; printed stickers no longer work".into());
+                        qr_img.set("".to_string());
+                    }
+                    Ok(resp) => message.set(format!(
+                        "Failed to revoke the QR code: {}",
+                        api_error(&resp).await.message
+                    )),
+                    Err(_) => message.set("Failed to revoke the QR code".into()),
+                }
+            });
+        })
+    };
+
+    let download = {
+        let message = message.clone();
+
+        Callback::from(move |(path, filename): (String, String)| {
+            let message = message.clone();
+            spawn_local(async move {
+                if let Err(error) = download(&format!("http://{}:{}{}", IP_ADDR, PORT, path), &filename).await {
+                    message.set(format!("Download failed: {}", error));
                 }
             });
         })
     };
 
     // search_unique_cols_vehicles.clone().emit("vehicle_type".to_string());
-    let id_vec = vec!["vehicle_types", "manufacturer", "model", "price", "data"];
+    let id_vec = ["vehicle_types", "manufacturer", "model", "price", "currency"];
 
     html! (
         <div class="container mx-auto p-4">
@@ -303,18 +529,44 @@ fn main_app() -> Html {
                 // <!-- Search Input for Desktop -->
                 <div class="hidden md:block relative">
                     <input type="text" placeholder="Search..." id="desktop-search"
-                        class="pl-10 p-2.5 w-full text-sm border-gray-300 rounded-lg bg-gray-50 dark:bg-gray-700 dark:border-gray-600 dark:text-white focus:ring-blue-500 focus:border-blue-500"/>
+                        class="pl-10 p-2.5 w-full text-sm border-gray-300 rounded-lg bg-gray-50 dark:bg-gray-700 dark:border-gray-600 dark:text-white focus:ring-blue-500 focus:border-blue-500"
+                        value={(*search_text).clone()} oninput={Callback::from({ let search_text = search_text.clone();
+                            move |e: InputEvent| {
+                                let input = e.target_dyn_into::<web_sys::HtmlInputElement>().unwrap();
+                                search_text.set(input.value());
+                            }
+                        })}/>
                     <button onclick={fuzzy_search_vehicles.clone().reform(|_| {})}
                         class="absolute inset-y-0 right-0 px-4 py-2 bg-gray-500 hover:bg-gray-700 text-white font-bold rounded-lg">
                         {"Search"}
                     </button>
                 </div>
+
+                // <!-- Login State -->
+                if let Some(session) = (*session).clone() {
+                    <span class="p-2.5 dark:text-white">{session.username}</span>
+                    <button onclick={logout}
+                        class="px-4 py-2 bg-gray-500 hover:bg-gray-700 text-white font-bold rounded-lg">
+                        {"Log out"}
+                    </button>
+                } else {
+                    <Link<Route> to={Route::Login}
+                        classes="px-4 py-2 bg-blue-500 hover:bg-blue-700 text-white font-bold rounded-lg">
+                        {"Log in"}
+                    </Link<Route>>
+                }
             </div>
     
             // <!-- Search Input for Mobile (Initially Hidden) -->
             <div id="mobile-search" class="hidden w-full mt-2 md:hidden">
                 <input type="text" placeholder="Search..." id="mobile-search-input"
-                    class="w-full pl-10 p-2.5 text-sm border-gray-300 rounded-lg bg-gray-50 dark:bg-gray-700 dark:border-gray-600 dark:text-white focus:ring-blue-500 focus:border-blue-500"/>
+                    class="w-full pl-10 p-2.5 text-sm border-gray-300 rounded-lg bg-gray-50 dark:bg-gray-700 dark:border-gray-600 dark:text-white focus:ring-blue-500 focus:border-blue-500"
+                    value={(*search_text).clone()} oninput={Callback::from({ let search_text = search_text.clone();
+                        move |e: InputEvent| {
+                            let input = e.target_dyn_into::<web_sys::HtmlInputElement>().unwrap();
+                            search_text.set(input.value());
+                        }
+                    })}/>
                 <button onclick={fuzzy_search_vehicles.clone().reform(|_| {})}
                     class="mt-2 w-full py-2 bg-gray-500 hover:bg-gray-700 text-white font-bold rounded-lg">
                     {"Search"}
@@ -328,57 +580,64 @@ fn main_app() -> Html {
         <h1 class="text-3xl font-bold text-blue-500 mb-6 text-center">{"Vehicle Management"}</h1>
                 {for id_vec.iter().map(|id| {
                     html!(<datalist id={*id}>
-                    {for (*suggestion_list).iter().map(|row| {
-                        html!(<option value={format!("{}", row)}/>)
+                    {for (*suggestion_list).iter().map(|facet: &Facet| {
+                        html!(<option value={facet.value.clone()} label={format!("{} ({})", facet.value, facet.count)}/>)
                     })}
                 </datalist>)
                 })}
 
         // <!-- Form for Vehicle Inputs -->
         <div class="mb-4 grid grid-cols-1 sm:grid-cols-2 md:grid-cols-3 gap-4">
-            <input list="vehicle_types" placeholder="Vehicle Type" value={vehicle_state.0.clone()} 
+            <input list="vehicle_types" placeholder="Vehicle Type" value={vehicle_state.vehicle_type.clone()} 
                 class="border rounded-lg p-2.5"
                 onfocus={search_unique_cols_vehicles.clone().reform(|_| {"vehicle_type".to_string()})} 
                 oninput={Callback::from({ let vehicle_state = vehicle_state.clone(); move |e: InputEvent| {
                     let input = e.target_dyn_into::<web_sys::HtmlInputElement>().unwrap();
-                    vehicle_state.set((input.value(), vehicle_state.1.clone(), vehicle_state.2.clone(), vehicle_state.3.clone(), vehicle_state.4.clone(), vehicle_state.5));
+                    vehicle_state.set(VehicleForm { vehicle_type: input.value(), ..(*vehicle_state).clone() });
                 }})}/>
 
-            <input list="manufacturer" placeholder="Manufacturer" value={vehicle_state.1.clone()} 
+            <input list="manufacturer" placeholder="Manufacturer" value={vehicle_state.manufacturer.clone()} 
                 class="border rounded-lg p-2.5"
                 onfocus={search_unique_cols_vehicles.clone().reform(|_| {"manufacturer".to_string()})} 
                 oninput={Callback::from({ let vehicle_state = vehicle_state.clone(); move |e: InputEvent| {
                     let input = e.target_dyn_into::<web_sys::HtmlInputElement>().unwrap();
-                    vehicle_state.set((vehicle_state.0.clone(), input.value(), vehicle_state.2.clone(), vehicle_state.3.clone(), vehicle_state.4.clone(), vehicle_state.5));
+                    vehicle_state.set(VehicleForm { manufacturer: input.value(), ..(*vehicle_state).clone() });
                 }})}/>
 
-            <input list="model" placeholder="Model" value={vehicle_state.2.clone()} 
+            <input list="model" placeholder="Model" value={vehicle_state.model.clone()} 
                 class="border rounded-lg p-2.5"
                 onfocus={search_unique_cols_vehicles.clone().reform(|_| {"model".to_string()})} 
                 oninput={Callback::from({ let vehicle_state = vehicle_state.clone(); move |e: InputEvent| {
                     let input = e.target_dyn_into::<web_sys::HtmlInputElement>().unwrap();
-                    vehicle_state.set((vehicle_state.0.clone(), vehicle_state.1.clone(), input.value(), vehicle_state.3.clone(), vehicle_state.4.clone(), vehicle_state.5));
+                    vehicle_state.set(VehicleForm { model: input.value(), ..(*vehicle_state).clone() });
                 }})}/>
 
-            <input list="price" placeholder="Price" value={vehicle_state.3.clone()} 
+            <input list="price" placeholder="Price" value={vehicle_state.price.clone()} 
                 class="border rounded-lg p-2.5"
                 onfocus={search_unique_cols_vehicles.clone().reform(|_| {"price".to_string()})} 
                 oninput={Callback::from({ let vehicle_state = vehicle_state.clone(); move |e: InputEvent| {
                     let input = e.target_dyn_into::<web_sys::HtmlInputElement>().unwrap();
-                    vehicle_state.set((vehicle_state.0.clone(), vehicle_state.1.clone(), vehicle_state.2.clone(), input.value(), vehicle_state.4.clone(), vehicle_state.5));
+                    vehicle_state.set(VehicleForm { price: input.value(), ..(*vehicle_state).clone() });
+                }})}/>
+
+            <input list="currency" placeholder="Currency" value={vehicle_state.currency.clone()} 
+                class="border rounded-lg p-2.5"
+                onfocus={search_unique_cols_vehicles.clone().reform(|_| {"currency".to_string()})} 
+                oninput={Callback::from({ let vehicle_state = vehicle_state.clone(); move |e: InputEvent| {
+                    let input = e.target_dyn_into::<web_sys::HtmlInputElement>().unwrap();
+                    vehicle_state.set(VehicleForm { currency: input.value(), ..(*vehicle_state).clone() });
                 }})}/>
 
-            <input list="data" placeholder="Data" value={vehicle_state.4.clone()} 
+            <input placeholder="Specs, e.g. color=red, year=2018" value={vehicle_state.data.clone()} 
                 class="border rounded-lg p-2.5"
-                onfocus={search_unique_cols_vehicles.clone().reform(|_| {"data".to_string()})} 
                 oninput={Callback::from({ let vehicle_state = vehicle_state.clone(); move |e: InputEvent| {
                     let input = e.target_dyn_into::<web_sys::HtmlInputElement>().unwrap();
-                    vehicle_state.set((vehicle_state.0.clone(), vehicle_state.1.clone(), vehicle_state.2.clone(), vehicle_state.3.clone(), input.value(), vehicle_state.5));
+                    vehicle_state.set(VehicleForm { data: input.value(), ..(*vehicle_state).clone() });
                 }})}/>
 
-            <button onclick={if vehicle_state.5.is_some() { update_vehicle.clone() } else { create_vehicle.clone() }}
+            <button onclick={if vehicle_state.id.is_some() { update_vehicle.clone() } else { create_vehicle.clone() }}
                 class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded-lg">
-                { if vehicle_state.5.is_some() {"Update Vehicle"} else {"Create Vehicle"}}
+                { if vehicle_state.id.is_some() {"Update Vehicle"} else {"Create Vehicle"}}
             </button>
         </div>
 
@@ -387,46 +646,141 @@ fn main_app() -> Html {
             <p class="text-green-500 text-center">{&*message}</p>
         }
 
-        // <!-- Fetch Vehicles Button -->
-        <div class="text-center">
+        // <!-- Fetch Vehicles, Export and Label Buttons -->
+        <div class="flex items-center justify-center space-x-2">
             <button onclick={get_vehicles.reform(|_| {})}
                 class="bg-gray-500 hover:bg-gray-700 text-white font-bold py-2 px-4 rounded-lg">
                 {"Fetch Vehicle List"}
             </button>
+            <select class="border rounded-lg p-2"
+                onchange={Callback::from({ let export_format = export_format.clone(); move |e: Event| {
+                    let select = e.target_dyn_into::<web_sys::HtmlSelectElement>().unwrap();
+                    export_format.set(select.value());
+                }})}>
+                <option value="csv" selected={*export_format == "csv"}>{"CSV"}</option>
+                <option value="jsonl" selected={*export_format == "jsonl"}>{"JSON Lines"}</option>
+                <option value="xlsx" selected={*export_format == "xlsx"}>{"Excel (XLSX)"}</option>
+            </select>
+            <button onclick={download.reform({
+                    let format = (*export_format).clone();
+                    let query = shown_query(&shown_search);
+                    query.append("format", &format);
+                    let query = String::from(query.to_string());
+                    move |_| (
+                        format!("/api/vehicles/export?{}", query),
+                        format!("vehicles.{}", format),
+                    )
+                })}
+                class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded-lg">
+                {"Export"}
+            </button>
+            <button onclick={download.reform({
+                    let query = String::from(shown_query(&shown_search).to_string());
+                    move |_| (
+                        format!("/api/vehicles/labels?{}", query),
+                        "vehicle-labels.pdf".to_string(),
+                    )
+                })}
+                class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded-lg">
+                {"Print QR labels"}
+            </button>
         </div>
 
         // <!-- QR Image -->
         <div class="text-center my-4">
-            <img class="inline-block" src={format!("data:image/png;base64,{}", qr_img.clone().to_string())}/>
+            <img class="inline-block" src={format!("data:image/png;base64,{}", *qr_img)}/>
         </div>
 
         // <!-- Vehicle List -->
         <h2 class="text-2xl font-bold text-gray-700 mb-4 text-center">{"Vehicle List"}</h2>
+        if let Some(page) = (*page).clone() {
+            <div class="flex items-center justify-center space-x-4 mb-4">
+                <button disabled={page.offset == 0}
+                    onclick={load_page.reform(move |_| (page.offset - PAGE_SIZE).max(0))}
+                    class="bg-gray-500 hover:bg-gray-700 disabled:opacity-50 text-white font-bold py-1 px-3 rounded-lg">
+                    {"Previous"}
+                </button>
+                <span>{format!("{}-{} of {}", (page.offset + 1).min(page.total), page.offset + page.items.len() as i64, page.total)}</span>
+                <button disabled={page.next_offset.is_none()}
+                    onclick={load_page.reform(move |_| page.next_offset.unwrap_or(page.offset))}
+                    class="bg-gray-500 hover:bg-gray-700 disabled:opacity-50 text-white font-bold py-1 px-3 rounded-lg">
+                    {"Next"}
+                </button>
+            </div>
+        }
         <ul class="list-disc pl-5">
             {for (*vehicles).iter().map(|vehicle| {
+                let role = (*session).as_ref().map(|session| session.role).unwrap_or_default();
                 let vehicle_id = vehicle.id;
+                let version = vehicle.version;
                 html!(
                     <li class="mb-2">
                         <span class="font-semibold">
                             { format!("ID: {}, Vehicle Type: {}, Manufacturer: {}, Model: {}, Price: {}, Data: {}", 
-                            vehicle.id, vehicle.vehicle_type, vehicle.manufacturer, vehicle.model, vehicle.price, vehicle.data)}
+                            vehicle.id, vehicle.vehicle_type, vehicle.manufacturer, vehicle.model, vehicle.price_text(), specs_to_text(&vehicle.data))}
                         </span>
-                        <button onclick={delete_vehicle.clone().reform(move |_| vehicle_id)}
-                            class="ml-4 bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-2 rounded-lg">
-                            {"Delete"}
-                        </button>
-                        <button onclick={edit_vehicle.clone().reform(move |_| vehicle_id)}
-                            class="ml-4 bg-yellow-500 hover:bg-yellow-700 text-white font-bold py-1 px-2 rounded-lg">
-                            {"Edit"}
-                        </button>
+                        if role >= Role::Admin {
+                            <button onclick={delete_vehicle.clone().reform(move |_| (vehicle_id, version))}
+                                class="ml-4 bg-red-500 hover:bg-red-700 text-white font-bold py-1 px-2 rounded-lg">
+                                {"Delete"}
+                            </button>
+                        }
+                        if role >= Role::Editor {
+                            <button onclick={edit_vehicle.clone().reform(move |_| vehicle_id)}
+                                class="ml-4 bg-yellow-500 hover:bg-yellow-700 text-white font-bold py-1 px-2 rounded-lg">
+                                {"Edit"}
+                            </button>
+                        }
                         <button onclick={show_vehicle_qr.clone().reform(move |_| vehicle_id)}
                             class="ml-4 bg-green-500 hover:bg-green-700 text-white font-bold py-1 px-2 rounded-lg">
                             {"Generate QR"}
                         </button>
+                        if role >= Role::Editor {
+                            <button onclick={regenerate_qr.clone().reform(move |_| vehicle_id)}
+                                class="ml-4 bg-green-500 hover:bg-green-700 text-white font-bold py-1 px-2 rounded-lg">
+                                {"New QR"}
+                            </button>
+                            <button onclick={revoke_qr.clone().reform(move |_| vehicle_id)}
+                                class="ml-4 bg-gray-500 hover:bg-gray-700 text-white font-bold py-1 px-2 rounded-lg">
+                                {"Revoke QR"}
+                            </button>
+                        }
                     </li>
                 )
             })}
         </ul>
+
+        <button onclick={toggle_trash}
+            class="mt-4 bg-gray-500 hover:bg-gray-700 text-white font-bold py-1 px-3 rounded-lg">
+            { if trash.is_some() { "Hide trash" } else { "Show trash" } }
+        </button>
+        if let Some(trashed) = (*trash).clone() {
+            <h2 class="text-2xl font-bold text-gray-700 my-4 text-center">{"Trash"}</h2>
+            if trashed.is_empty() {
+                <p class="text-center text-gray-500">{"The trash is empty"}</p>
+            }
+            <ul class="list-disc pl-5">
+                {for trashed.iter().map(|entry| {
+                    let role = (*session).as_ref().map(|session| session.role).unwrap_or_default();
+                    let vehicle_id = entry.vehicle.id;
+                    html!(
+                        <li class="mb-2">
+                            <span class="font-semibold">
+                                { format!("ID: {}, {} {} {}, deleted {}{}",
+                                vehicle_id, entry.vehicle.vehicle_type, entry.vehicle.manufacturer, entry.vehicle.model,
+                                entry.deleted_at, entry.deleted_by.as_ref().map(|by| format!(" by {}", by)).unwrap_or_default())}
+                            </span>
+                            if role >= Role::Editor {
+                                <button onclick={restore_vehicle.clone().reform(move |_| vehicle_id)}
+                                    class="ml-4 bg-blue-500 hover:bg-blue-700 text-white font-bold py-1 px-2 rounded-lg">
+                                    {"Restore"}
+                                </button>
+                            }
+                        </li>
+                    )
+                })}
+            </ul>
+        }
     </div>
 
 </div>
@@ -613,61 +967,456 @@ fn main_app() -> Html {
     )
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Price {
+    amount: String,
+    currency: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 struct Vehicle {
     id: i32,
+    vehicle_type: String,
+    manufacturer: String,
+    model: String,
+    price: Option<Price>,
+    #[serde(default)]
+    price_legacy: Option<String>,
+    data: Map<String, Value>,
+    #[serde(default)]
+    version: i32,
+}
+
+impl Vehicle {
+    fn price_text(&self) -> String {
+        match (&self.price, &self.price_legacy) {
+            (Some(price), _) => format!("{} {}", price.amount, price.currency),
+            (None, Some(legacy)) => format!("{} (needs review)", legacy),
+            (None, None) => "".to_string(),
+        }
+    }
+}
+
+/// A vehicle as the Info page shows it. The public QR page leaves out the
+/// fields that are internal, so everything may be missing.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+struct VehicleView {
+    id: Option<i32>,
+    vehicle_type: Option<String>,
+    manufacturer: Option<String>,
+    model: Option<String>,
+    price: Option<Price>,
+    price_legacy: Option<String>,
+    data: Map<String, Value>,
+}
+
+impl VehicleView {
+    /// The price row, if the price may be shown at all.
+    fn price_text(&self) -> Option<String> {
+        match (&self.price, &self.price_legacy) {
+            (Some(price), _) => Some(format!("{} {}", price.amount, price.currency)),
+            (None, Some(legacy)) => Some(format!("{} (needs review)", legacy)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// A distinct column value and how many vehicles have it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Facet {
+    value: String,
+    count: i64,
+}
+
+/// One page of `GET /api/vehicles`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct VehiclePage {
+    items: Vec<Vehicle>,
+    total: i64,
+    limit: i64,
+    offset: i64,
+    next_offset: Option<i64>,
+}
+
+/// A deleted vehicle as listed by `GET /api/vehicles/trash`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct TrashedVehicle {
+    #[serde(flatten)]
+    vehicle: Vehicle,
+    deleted_at: String,
+    deleted_by: Option<String>,
+}
+
+/// One page of `GET /api/vehicles/trash`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct TrashPage {
+    items: Vec<TrashedVehicle>,
+    total: i64,
+}
+
+/// One entry of `GET /api/vehicles/<id>/history`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct HistoryEntry {
+    id: i64,
+    action: String,
+    changes: BTreeMap<String, FieldChange>,
+    actor: Option<String>,
+    changed_at: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct FieldChange {
+    before: Value,
+    after: Value,
+}
+
+fn history_value_text(value: &Value) -> String {
+    match value {
+        Value::Null => "(none)".to_string(),
+        other => spec_value_text(other),
+    }
+}
+
+/// Contents of the create/edit form. `id` and `version` are set while
+/// editing, and `original` holds the request body the form started from so
+/// only edited fields are sent.
+#[derive(Clone, Debug, PartialEq)]
+struct VehicleForm {
     vehicle_type: String,
     manufacturer: String,
     model: String,
     price: String,
+    currency: String,
     data: String,
+    id: Option<i32>,
+    version: Option<i32>,
+    original: Option<Map<String, Value>>,
+}
+
+impl Default for VehicleForm {
+    fn default() -> Self {
+        VehicleForm {
+            vehicle_type: "".to_string(),
+            manufacturer: "".to_string(),
+            model: "".to_string(),
+            price: "".to_string(),
+            currency: "USD".to_string(),
+            data: "".to_string(),
+            id: None,
+            version: None,
+            original: None,
+        }
+    }
+}
+
+impl From<&Vehicle> for VehicleForm {
+    fn from(vehicle: &Vehicle) -> Self {
+        let price = vehicle.price.clone();
+        let mut form = VehicleForm {
+            vehicle_type: vehicle.vehicle_type.clone(),
+            manufacturer: vehicle.manufacturer.clone(),
+            model: vehicle.model.clone(),
+            price: price.as_ref().map(|p| p.amount.clone()).unwrap_or_default(),
+            currency: price.map(|p| p.currency).unwrap_or_else(|| "USD".to_string()),
+            data: specs_to_text(&vehicle.data),
+            id: Some(vehicle.id),
+            version: Some(vehicle.version),
+            original: None,
+        };
+        form.original = form.to_json().ok();
+        form
+    }
+}
+
+impl VehicleForm {
+    fn to_json(&self) -> Result<Map<String, Value>, String> {
+        let mut body = Map::new();
+        body.insert("vehicle_type".into(), self.vehicle_type.clone().into());
+        body.insert("manufacturer".into(), self.manufacturer.clone().into());
+        body.insert("model".into(), self.model.clone().into());
+        body.insert(
+            "price".into(),
+            serde_json::json!({"amount": self.price.trim(), "currency": self.currency.trim().to_uppercase()}),
+        );
+        body.insert("data".into(), Value::Object(text_to_specs(&self.data)?));
+        Ok(body)
+    }
+
+    /// Fields that differ from what the form was opened with.
+    fn changes(&self) -> Result<Map<String, Value>, String> {
+        let mut body = self.to_json()?;
+        if let Some(original) = &self.original {
+            body.retain(|key, value| original.get(key) != Some(value));
+        }
+        Ok(body)
+    }
+}
+
+/// Renders specs as the `key=value, key=value` text used by the form.
+fn specs_to_text(specs: &Map<String, Value>) -> String {
+    specs
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, spec_value_text(value)))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn spec_value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Parses `key=value, key=value`. Numbers and booleans keep their JSON type so
+/// range filters work on them.
+fn text_to_specs(text: &str) -> Result<Map<String, Value>, String> {
+    let mut specs = Map::new();
+    for pair in text.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("Spec `{}` should look like key=value", pair))?;
+        let value = value.trim();
+        let value = match serde_json::from_str::<Value>(value) {
+            Ok(v @ (Value::Number(_) | Value::Bool(_))) => v,
+            _ => Value::String(value.to_string()),
+        };
+        specs.insert(key.trim().to_string(), value);
+    }
+    Ok(specs)
+}
+
+/// Error body returned by every failing backend route.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct ApiError {
+    code: String,
+    message: String,
+    /// Latest state of the vehicle when a write was rejected as stale.
+    #[serde(default)]
+    current: Option<Vehicle>,
+}
+
+async fn api_error(resp: &Response) -> ApiError {
+    resp.json().await.unwrap_or_else(|_| ApiError {
+        code: "unknown".to_string(),
+        message: resp.status_text(),
+        current: None,
+    })
+}
+
+/// What the signed-in user may do; each role includes the ones before it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+enum Role {
+    #[default]
+    Viewer,
+    Editor,
+    Admin,
+}
+
+/// A login, as returned by `/api/auth/login` and kept in local storage.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Session {
+    token: String,
+    username: String,
+    /// Logins kept from before roles existed count as viewers until the
+    /// next sign-in.
+    #[serde(default)]
+    role: Role,
+}
+
+fn stored_session() -> Option<Session> {
+    LocalStorage::get(SESSION_KEY).ok()
+}
+
+/// Adds the stored session token, if there is one. Every route but the
+/// public slug lookup and login needs it.
+fn signed(request: Request) -> Request {
+    match stored_session() {
+        Some(session) => request.header("Authorization", &format!("Bearer {}", session.token)),
+        None => request,
+    }
+}
+
+/// Query selecting what the list shows, searched or not, so exports and
+/// labels cover the same vehicles.
+fn shown_query(shown_search: &Option<String>) -> web_sys::UrlSearchParams {
+    let query = web_sys::UrlSearchParams::new().unwrap();
+    if let Some(search) = shown_search {
+        query.append("fuzzy", search);
+    }
+    query
+}
+
+/// Fetches a file with the session token and saves it under `filename`.
+/// Plain links can't send the token.
+async fn download(url: &str, filename: &str) -> Result<(), String> {
+    let resp = signed(Request::get(url))
+        .send()
+        .await
+        .map_err(|_| "the server is unreachable, try again later".to_string())?;
+    if !resp.ok() {
+        return Err(api_error(&resp).await.message);
+    }
+    let bytes = resp.binary().await.map_err(|e| e.to_string())?;
+    let content_type = resp.headers().get("content-type");
+    let file = ObjectUrl::from(Blob::new_with_options(bytes.as_slice(), content_type.as_deref()));
+    let link: web_sys::HtmlAnchorElement = gloo::utils::document()
+        .create_element("a")
+        .map_err(|_| "the browser can't save files".to_string())?
+        .unchecked_into();
+    link.set_href(&file);
+    link.set_download(filename);
+    link.click();
+    // Revoking the URL right away can cancel the download in some browsers.
+    Timeout::new(60_000, move || drop(file)).forget();
+    Ok(())
+}
+
+#[function_component(Login)]
+fn login_page() -> Html {
+    let username = use_state(String::new);
+    let password = use_state(String::new);
+    let error = use_state(|| None as Option<String>);
+    let navigator = use_navigator().unwrap();
+
+    let login = {
+        let username = username.clone();
+        let password = password.clone();
+        let error = error.clone();
+
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            let body = serde_json::json!({ "username": *username, "password": *password });
+            let error = error.clone();
+            let navigator = navigator.clone();
+
+            spawn_local(async move {
+                let response = Request::post(&format!("http://{}:{}/api/auth/login", IP_ADDR, PORT))
+                    .header("Content-Type", "application/json")
+                    .body(body.to_string())
+                    .send()
+                    .await;
+                match response {
+                    Ok(resp) if resp.ok() => match resp.json::<Session>().await {
+                        Ok(session) if LocalStorage::set(SESSION_KEY, &session).is_ok() => {
+                            navigator.push(&Route::Home)
+                        }
+                        _ => error.set(Some("Could not keep the login in this browser".into())),
+                    },
+                    Ok(resp) => error.set(Some(api_error(&resp).await.message)),
+                    Err(_) => error.set(Some("The server is unreachable, try again later".into())),
+                }
+            });
+        })
+    };
+
+    html!(
+        <div class="container mx-auto max-w-sm p-4">
+            <h1 class="text-2xl font-bold text-center mb-6">{"Log in"}</h1>
+            <form onsubmit={login} class="flex flex-col space-y-2">
+                <input type="text" placeholder="Username" value={(*username).clone()}
+                    class="border rounded-lg p-2.5"
+                    oninput={Callback::from({ let username = username.clone(); move |e: InputEvent| {
+                        let input = e.target_dyn_into::<web_sys::HtmlInputElement>().unwrap();
+                        username.set(input.value());
+                    }})}/>
+                <input type="password" placeholder="Password" value={(*password).clone()}
+                    class="border rounded-lg p-2.5"
+                    oninput={Callback::from({ let password = password.clone(); move |e: InputEvent| {
+                        let input = e.target_dyn_into::<web_sys::HtmlInputElement>().unwrap();
+                        password.set(input.value());
+                    }})}/>
+                <button type="submit"
+                    class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded-lg">
+                    {"Log in"}
+                </button>
+            </form>
+            if let Some(error) = (*error).clone() {
+                <p class="text-red-500 text-center mt-4">{error}</p>
+            }
+        </div>
+    )
 }
 
 #[derive(Properties, PartialEq)]
 pub struct Props {
+    /// The vehicle's ID, or its public slug when `public` is set.
     pub id: String,
+    #[prop_or_default]
+    pub public: bool,
 }
 
 #[function_component(Info)]
 fn main_app(props: &Props) -> Html {
-    let Props { id } = props;
-    let id = use_state(|| id.to_string());
-    let vehicle = use_state_eq(|| Vehicle {
-        id: -1,
-        data: "".to_string(),
-        vehicle_type: "".to_string(),
-        manufacturer: "".to_string(),
-        model: "".to_string(),
-        price: "".to_string(),
-    });
-
-    let get_cur_vehicle = {
+    let vehicle = use_state_eq(VehicleView::default);
+    let error = use_state_eq(|| None as Option<String>);
+    let history = use_state_eq(Vec::<HistoryEntry>::new);
+
+    // Loads the vehicle when the page opens and again only when it's
+    // navigated to another vehicle, not on every render.
+    {
         let vehicle = vehicle.clone();
-        let id = id.clone();
+        let error = error.clone();
+        let history = history.clone();
 
-        Callback::from(move |_| {
-            let vehicle = vehicle.clone();
+        use_effect_with((props.id.clone(), props.public), move |(id, public)| {
             let id = id.clone();
+            let public = *public;
+            history.set(Vec::new());
 
             spawn_local(async move {
-                match Request::get(&format!("http://{}:{}/api/vehicles/{}", IP_ADDR, PORT, *id))
-                    .send()
-                    .await
-                {
+                let url = match public {
+                    true => format!("http://{}:{}/api/public/vehicles/{}", IP_ADDR, PORT, id),
+                    false => format!("http://{}:{}/api/vehicles/{}", IP_ADDR, PORT, id),
+                };
+                match signed(Request::get(&url)).send().await {
                     Ok(resp) if resp.ok() => {
-                        let fetched_vehicle: Vehicle = resp.json().await.unwrap();
+                        let fetched_vehicle: VehicleView = resp.json().await.unwrap_or_default();
                         vehicle.set(fetched_vehicle);
+                        error.set(None);
+                    }
+                    Ok(resp) if resp.status() == 404 => {
+                        error.set(Some("No vehicle is registered under this code".into()))
+                    }
+                    Ok(resp) => error.set(Some(format!(
+                        "The server could not load this vehicle: {}",
+                        api_error(&resp).await.message
+                    ))),
+                    Err(_) => error.set(Some("The server is unreachable, try again later".into())),
+                }
+                // The history is for staff, not for whoever scans a sticker.
+                if public {
+                    return;
+                }
+                if let Ok(resp) = signed(Request::get(&format!(
+                    "http://{}:{}/api/vehicles/{}/history",
+                    IP_ADDR, PORT, id
+                )))
+                .send()
+                .await
+                {
+                    if resp.ok() {
+                        history.set(resp.json().await.unwrap_or_default());
                     }
-                    _ => {}
                 }
             });
-        })
-    };
+            || ()
+        });
+    }
 
     let display_vehicle_data = move || -> Html {
-        let get_cur_vehicle = get_cur_vehicle.clone();
-        get_cur_vehicle.emit(());
+        if let Some(error) = (*error).clone() {
+            return html!(
+                <div class="container mx-auto">
+                    <h1 class="text-2xl font-bold text-center mb-6">{"Vehicle Information"}</h1>
+                    <p class="text-red-500 text-center">{error}</p>
+                </div>
+            );
+        }
         let vehicle = (*vehicle).clone();
+        let price = vehicle.price_text();
         // html!(format!(
         //     "ID: {}, Vehicle Type: {}, Manufacturer: {}, Model: {}, Price: {}, Data: {}",
         //     vehicle.id,
@@ -684,33 +1433,49 @@ fn main_app(props: &Props) -> Html {
             <div class="bg-white shadow-md rounded-lg p-6">
                 <table class="min-w-full">
                     <tbody>
-                        <tr class="border-b">
-                            <td class="py-3 px-4 font-semibold">{"ID:"}</td>
-                            <td class="py-3 px-4">{vehicle.id}</td>
-                        </tr>
-                        <tr class="border-b">
-                            <td class="py-3 px-4 font-semibold">{"Vehicle Type:"}</td>
-                            <td class="py-3 px-4">{vehicle.vehicle_type}</td>
-                        </tr>
-                        <tr class="border-b">
-                            <td class="py-3 px-4 font-semibold">{"Manufacturer:"}</td>
-                            <td class="py-3 px-4">{vehicle.manufacturer}</td>
-                        </tr>
-                        <tr class="border-b">
-                            <td class="py-3 px-4 font-semibold">{"Model:"}</td>
-                            <td class="py-3 px-4">{vehicle.model}</td>
-                        </tr>
-                        <tr class="border-b">
-                            <td class="py-3 px-4 font-semibold">{"Price:"}</td>
-                            <td class="py-3 px-4">{vehicle.price}</td>
-                        </tr>
-                        <tr class="border-b">
-                            <td class="py-3 px-4 font-semibold">{"Data:"}</td>
-                            <td class="py-3 px-4">{vehicle.data}</td>
-                        </tr>
+                        {for [
+                            ("ID:", vehicle.id.map(|id| id.to_string())),
+                            ("Vehicle Type:", vehicle.vehicle_type.clone()),
+                            ("Manufacturer:", vehicle.manufacturer.clone()),
+                            ("Model:", vehicle.model.clone()),
+                            ("Price:", price),
+                        ].into_iter().filter_map(|(label, value)| value.map(|value| html!(
+                            <tr class="border-b">
+                                <td class="py-3 px-4 font-semibold">{label}</td>
+                                <td class="py-3 px-4">{value}</td>
+                            </tr>
+                        )))}
+                        {for vehicle.data.iter().map(|(key, value)| html!(
+                            <tr class="border-b">
+                                <td class="py-3 px-4 font-semibold">{format!("{}:", key)}</td>
+                                <td class="py-3 px-4">{spec_value_text(value)}</td>
+                            </tr>
+                        ))}
                     </tbody>
                 </table>
             </div>
+
+            if !history.is_empty() {
+                <h2 class="text-xl font-bold text-center my-6">{"History"}</h2>
+                <ol class="border-l-2 border-gray-300 ml-4">
+                    {for history.iter().rev().map(|entry| html!(
+                        <li class="mb-6 ml-4">
+                            <p class="text-sm text-gray-500">
+                                {format!("{} · {} by {}", entry.changed_at, entry.action,
+                                    entry.actor.as_deref().unwrap_or("unknown"))}
+                            </p>
+                            <ul class="text-sm">
+                                {for entry.changes.iter().map(|(field, change)| html!(
+                                    <li>
+                                        <span class="font-semibold">{format!("{}: ", field)}</span>
+                                        {format!("{} → {}", history_value_text(&change.before), history_value_text(&change.after))}
+                                    </li>
+                                ))}
+                            </ul>
+                        </li>
+                    ))}
+                </ol>
+            }
         </div>
         )
     
@@ -726,7 +1491,11 @@ fn main_app(props: &Props) -> Html {
 fn switch(route: Route) -> Html {
     match route {
         Route::Home => html!(<App/>),
-        Route::Info { id } => html! (<Info id={id}/>),
+        Route::Login => html!(<Login/>),
+        // Looking vehicles up by id is for staff; stickers link to `Public`.
+        Route::Info { id } if stored_session().is_some() => html! (<Info id={id}/>),
+        Route::Info { .. } => html! (<Redirect<Route> to={Route::Login}/>),
+        Route::Public { slug } => html! (<Info id={slug} public=true/>),
     }
 }
 